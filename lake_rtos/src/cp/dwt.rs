@@ -0,0 +1,58 @@
+//! # Data watchpoint and trace unit (DWT)
+//!
+//! [Architecture Reference Manual](https://developer.arm.com/documentation/ddi0403/latest)
+//! Section C1.8 - Only the cycle counter is used.
+use crate::util::register::Register;
+
+use super::{DEMCR, DWT};
+
+/// Data watchpoint and trace registers
+#[repr(C)]
+#[derive(Debug)]
+struct Dwt {
+    /// Control register (RW)
+    dwt_ctrl: Register,
+    /// Cycle count register (RW)
+    dwt_cyccnt: Register,
+}
+
+/// Data watchpoint and trace unit
+///
+/// The cycle counter counts every clock cycle of the processor and wraps around on overflow.
+/// Differences of two counts are therefore valid as long as they are calculated with
+/// wrapping arithmetic.
+#[derive(Debug)]
+pub struct DataWatchpointTrace {
+    p: &'static mut Dwt,
+    demcr: &'static mut Register,
+}
+
+#[allow(dead_code)]
+impl DataWatchpointTrace {
+    pub(super) fn init() -> DataWatchpointTrace {
+        DataWatchpointTrace {
+            p: unsafe { &mut *(DWT as *mut Dwt) },
+            demcr: unsafe { &mut *(DEMCR as *mut Register) },
+        }
+    }
+
+    /// Enables the trace unit by setting `Bit 24 TRCENA` of the debug exception and monitor
+    /// control register and starts the cycle counter from 0.
+    pub fn enable_cycle_counter(&mut self) -> &mut DataWatchpointTrace {
+        self.demcr.set_bit(24);
+        self.p.dwt_cyccnt.replace_bits(0, 0, 32);
+        self.p.dwt_ctrl.set_bit(0);
+        self
+    }
+
+    /// Stops the cycle counter.
+    pub fn disable_cycle_counter(&mut self) -> &mut DataWatchpointTrace {
+        self.p.dwt_ctrl.clear_bit(0);
+        self
+    }
+
+    /// Returns the current value of the cycle counter.
+    pub fn cycle_count(&mut self) -> u32 {
+        self.p.dwt_cyccnt.read()
+    }
+}
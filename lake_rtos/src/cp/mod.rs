@@ -1,11 +1,18 @@
 //! # Core Peripherals
+pub mod dwt;
+pub mod mpu;
+pub mod scb;
 pub mod stk;
 
 use core::mem::replace;
 
-use self::stk::SystemTimer;
+use self::{dwt::DataWatchpointTrace, mpu::MemoryProtectionUnit, stk::SystemTimer};
 
 const SYSTICK_TIMER: u32 = 0xE000_E010;
+const MPU: u32 = 0xE000_ED90;
+const DWT: u32 = 0xE000_1000;
+/// Debug exception and monitor control register
+const DEMCR: u32 = 0xE000_EDFC;
 
 /// Boolean flag for singleton pattern.
 static mut TAKEN: bool = false;
@@ -13,6 +20,8 @@ static mut TAKEN: bool = false;
 /// Contains the core peripherals. Unlike device peripherals there is no bus interface.
 pub struct CorePeripherals {
     stk: Option<SystemTimer>,
+    mpu: Option<MemoryProtectionUnit>,
+    dwt: Option<DataWatchpointTrace>,
 }
 
 impl CorePeripherals {
@@ -29,6 +38,8 @@ impl CorePeripherals {
 
         CorePeripherals {
             stk: Some(SystemTimer::init()),
+            mpu: Some(MemoryProtectionUnit::init()),
+            dwt: Some(DataWatchpointTrace::init()),
         }
     }
 
@@ -40,4 +51,16 @@ impl CorePeripherals {
             None
         }
     }
+
+    /// Singleton pattern
+    #[allow(dead_code)]
+    pub fn take_mpu(&mut self) -> Option<MemoryProtectionUnit> {
+        self.mpu.take()
+    }
+
+    /// Singleton pattern
+    #[allow(dead_code)]
+    pub fn take_dwt(&mut self) -> Option<DataWatchpointTrace> {
+        self.dwt.take()
+    }
 }
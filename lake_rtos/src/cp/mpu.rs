@@ -0,0 +1,294 @@
+//! # Memory protection unit (MPU)
+//!
+//! [Programming Manual](https://www.st.com/content/ccc/resource/technical/document/programming_manual/6c/3a/cb/e7/e4/ea/44/9b/DM00046982.pdf/files/DM00046982.pdf/jcr:content/translations/en.DM00046982.pdf)
+//! Section 4.2 - p.192
+use crate::util::register::Register;
+
+use super::MPU;
+
+extern "C" {
+    /// Data and instruction synchronization barrier. Required after changing the MPU
+    /// configuration.
+    fn __memory_barrier();
+}
+
+/// Smallest possible size of a region in bytes.
+pub const REGION_SIZE_MIN: u32 = 32;
+/// Start of the flash memory.
+pub const FLASH_BASE: u32 = 0x0800_0000;
+/// Size of the flash memory of the STM32F303VC in bytes.
+pub const FLASH_SIZE: u32 = 0x4_0000;
+/// Start of the peripheral region of the memory map.
+#[allow(dead_code)]
+pub const PERIPHERAL_BASE: u32 = 0x4000_0000;
+/// Start of the system region of the memory map, holding the core peripherals.
+#[allow(dead_code)]
+pub const SYSTEM_BASE: u32 = 0xE000_0000;
+/// Size of the peripheral and the system region of the memory map in bytes.
+#[allow(dead_code)]
+pub const DEVICE_REGION_SIZE: u32 = 0x2000_0000;
+
+/// Memory protection unit registers
+#[repr(C)]
+#[derive(Debug)]
+struct Mpu {
+    /// Type register (RO)
+    mpu_typer: Register,
+    /// Control register (RW)
+    mpu_ctrl: Register,
+    /// Region number register (RW)
+    mpu_rnr: Register,
+    /// Region base address register (RW)
+    mpu_rbar: Register,
+    /// Region attribute and size register (RW)
+    mpu_rasr: Register,
+}
+
+/// Access permissions of a region. See Programming Manual Table 43.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u32)]
+pub enum AccessPermission {
+    /// Any access generates a permission fault.
+    NoAccess = 0b000,
+    /// Privileged access only.
+    PrivilegedReadWrite = 0b001,
+    /// Unprivileged writes generate a permission fault.
+    PrivilegedReadWriteUserReadOnly = 0b010,
+    /// Full access.
+    FullAccess = 0b011,
+    /// Privileged read only.
+    PrivilegedReadOnly = 0b101,
+    /// Privileged and unprivileged read only.
+    ReadOnly = 0b110,
+}
+
+#[allow(dead_code)]
+impl AccessPermission {
+    /// Returns true when unprivileged software may read.
+    pub fn is_user_readable(&self) -> bool {
+        matches!(
+            self,
+            AccessPermission::PrivilegedReadWriteUserReadOnly
+                | AccessPermission::FullAccess
+                | AccessPermission::ReadOnly
+        )
+    }
+
+    /// Returns true when unprivileged software may write.
+    pub fn is_user_writable(&self) -> bool {
+        *self == AccessPermission::FullAccess
+    }
+}
+
+/// Memory type and cache policy of a region, written to TEX, S, C and B of the RASR. See
+/// Programming Manual Table 40 and Table 41.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoryAttributes {
+    /// Accesses happen in program order and complete before the next one starts.
+    StronglyOrdered,
+    /// Shareable device memory, for peripherals.
+    Device,
+    /// Normal memory, write-through and not shareable. TEX=0 C=1 B=0
+    Flash,
+    /// Normal memory, write-back and shareable. TEX=0 C=1 B=1 S=1
+    Sram,
+}
+
+impl MemoryAttributes {
+    /// Returns the bits `TEX[21:19]`, `S[18]`, `C[17]` and `B[16]` of the RASR.
+    fn rasr_bits(&self) -> u32 {
+        // (TEX, S, C, B)
+        let (tex, s, c, b) = match self {
+            MemoryAttributes::StronglyOrdered => (0b000, 0, 0, 0),
+            MemoryAttributes::Device => (0b000, 1, 0, 1),
+            MemoryAttributes::Flash => (0b000, 0, 1, 0),
+            MemoryAttributes::Sram => (0b000, 1, 1, 1),
+        };
+        tex << 19 | s << 18 | c << 17 | b << 16
+    }
+}
+
+/// A memory area that fits into a single region of the MPU.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryRegion {
+    base: u32,
+    size: u32,
+    permission: AccessPermission,
+    attributes: MemoryAttributes,
+    execute_never: bool,
+}
+
+#[allow(dead_code)]
+impl MemoryRegion {
+    /// Returns [None] unless `size` is a power of two of at least [REGION_SIZE_MIN] bytes and
+    /// `base` is aligned to `size`.
+    pub fn new(
+        base: u32,
+        size: u32,
+        permission: AccessPermission,
+        attributes: MemoryAttributes,
+        execute_never: bool,
+    ) -> Option<MemoryRegion> {
+        if size < REGION_SIZE_MIN || !size.is_power_of_two() || base & (size - 1) != 0 {
+            return None;
+        }
+        Some(MemoryRegion {
+            base,
+            size,
+            permission,
+            attributes,
+            execute_never,
+        })
+    }
+
+    /// Lowest address of the region.
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Size in bytes.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn permission(&self) -> AccessPermission {
+        self.permission
+    }
+
+    pub fn attributes(&self) -> MemoryAttributes {
+        self.attributes
+    }
+
+    /// Returns true when instructions must not be fetched from the region.
+    pub fn is_execute_never(&self) -> bool {
+        self.execute_never
+    }
+}
+
+/// Memory protection unit
+///
+/// Programming Manual Section 4.2
+///
+/// A region has to be at least [REGION_SIZE_MIN] bytes, its size a power of two and its base
+/// address aligned to its size. Regions with a higher number take priority over overlapping
+/// regions with a lower number.
+#[derive(Debug)]
+pub struct MemoryProtectionUnit {
+    p: &'static mut Mpu,
+}
+
+#[allow(dead_code)]
+impl MemoryProtectionUnit {
+    pub(super) fn init() -> MemoryProtectionUnit {
+        MemoryProtectionUnit {
+            p: unsafe { &mut *(MPU as *mut Mpu) },
+        }
+    }
+
+    /// Enables the MPU by setting `Bit 0 ENABLE`. With `Bit 2 PRIVDEFENA` privileged
+    /// software keeps the default memory map as background region.
+    pub fn enable(&mut self) -> &mut MemoryProtectionUnit {
+        self.p.mpu_ctrl.replace_bits(0, 0b101, 3);
+        unsafe { __memory_barrier() };
+        self
+    }
+
+    /// Disables the MPU.
+    pub fn disable(&mut self) -> &mut MemoryProtectionUnit {
+        unsafe { __memory_barrier() };
+        self.p.mpu_ctrl.clear_bit(0);
+        self
+    }
+
+    /// Configures and enables a region.
+    ///
+    /// # Arguments
+    ///
+    /// * `region` - Region number between 0 and 7.
+    /// * `base` - Base address, aligned to `size`.
+    /// * `size` - Size in bytes, a power of two and at least [REGION_SIZE_MIN].
+    /// * `permission` - The [AccessPermission] of the region.
+    /// * `attributes` - The [MemoryAttributes] of the region.
+    /// * `execute_never` - Instruction fetches generate a fault.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is not a power of two of at least [REGION_SIZE_MIN] bytes or `base` is not
+    /// aligned to it.
+    pub fn set_region(
+        &mut self,
+        region: u32,
+        base: u32,
+        size: u32,
+        permission: AccessPermission,
+        attributes: MemoryAttributes,
+        execute_never: bool,
+    ) -> &mut MemoryProtectionUnit {
+        assert!(
+            size >= REGION_SIZE_MIN && size.is_power_of_two() && base & (size - 1) == 0,
+            "invalid MPU region"
+        );
+        // region size = 2^(SIZE + 1)
+        let size_field = size.trailing_zeros() - 1;
+
+        self.p.mpu_rnr.replace_bits(0, region, 8);
+        self.p.mpu_rbar.replace_bits(5, base >> 5, 27);
+        self.p.mpu_rasr.replace_bits(
+            0,
+            (execute_never as u32) << 28
+                | (permission as u32) << 24
+                | attributes.rasr_bits()
+                | size_field << 1
+                | 0b1,
+            32,
+        );
+        unsafe { __memory_barrier() };
+        self
+    }
+
+    /// Configures and enables a region covering the whole 4 GB address space. Regions with a
+    /// higher number take priority over it.
+    pub fn set_background_region(
+        &mut self,
+        region: u32,
+        permission: AccessPermission,
+        attributes: MemoryAttributes,
+    ) -> &mut MemoryProtectionUnit {
+        self.p.mpu_rnr.replace_bits(0, region, 8);
+        self.p.mpu_rbar.replace_bits(5, 0, 27);
+        // region size = 2^(31 + 1)
+        self.p.mpu_rasr.replace_bits(
+            0,
+            (permission as u32) << 24 | attributes.rasr_bits() | 31 << 1 | 0b1,
+            32,
+        );
+        unsafe { __memory_barrier() };
+        self
+    }
+
+    /// Configures and enables a region covering the given [MemoryRegion].
+    pub fn set_memory_region(
+        &mut self,
+        region: u32,
+        memory_region: &MemoryRegion,
+    ) -> &mut MemoryProtectionUnit {
+        self.set_region(
+            region,
+            memory_region.base,
+            memory_region.size,
+            memory_region.permission,
+            memory_region.attributes,
+            memory_region.execute_never,
+        )
+    }
+
+    /// Disables a region.
+    pub fn disable_region(&mut self, region: u32) -> &mut MemoryProtectionUnit {
+        self.p.mpu_rnr.replace_bits(0, region, 8);
+        self.p.mpu_rasr.clear_bit(0);
+        unsafe { __memory_barrier() };
+        self
+    }
+}
@@ -0,0 +1,168 @@
+//! # System control block (SCB)
+//!
+//! [Programming Manual](https://www.st.com/content/ccc/resource/technical/document/programming_manual/6c/3a/cb/e7/e4/ea/44/9b/DM00046982.pdf/files/DM00046982.pdf/jcr:content/translations/en.DM00046982.pdf)
+//! Section 4.4 - p.220
+//!
+//! Only the registers required to handle faults are covered.
+use crate::util::register::Register;
+
+/// Interrupt control and state register (ICSR)
+const ICSR: u32 = 0xE000_ED04;
+/// Application interrupt and reset control register (AIRCR)
+const AIRCR: u32 = 0xE000_ED0C;
+/// System handler control and state register (SHCSR)
+const SHCSR: u32 = 0xE000_ED24;
+/// Configurable fault status register (CFSR). Consists of MMFSR, BFSR and UFSR.
+const CFSR: u32 = 0xE000_ED28;
+/// Hard fault status register (HFSR)
+const HFSR: u32 = 0xE000_ED2C;
+/// Memory management fault address register (MMFAR)
+const MMFAR: u32 = 0xE000_ED34;
+/// Bus fault address register (BFAR)
+const BFAR: u32 = 0xE000_ED38;
+
+/// `MMARVALID` of the MMFSR, MMFAR holds a valid address.
+const MMARVALID: u32 = 1 << 7;
+/// `BFARVALID` of the BFSR, BFAR holds a valid address.
+const BFARVALID: u32 = 1 << 15;
+/// Stacking, unstacking and lazy state preservation errors of MMFSR and BFSR.
+const STACKING_ERRORS: u32 = 0b11_1000 << 8 | 0b11_1000;
+
+/// Bits of the CFSR with a description. See Programming Manual Section 4.4.14.
+const CFSR_REASONS: [(u32, &str); 17] = [
+    (0, "instruction access violation"),
+    (1, "data access violation"),
+    (3, "unstacking error on exception return (MemManage)"),
+    (4, "stacking error on exception entry (MemManage)"),
+    (
+        5,
+        "lazy floating-point state preservation error (MemManage)",
+    ),
+    (8, "instruction bus error"),
+    (9, "precise data bus error"),
+    (10, "imprecise data bus error"),
+    (11, "unstacking error on exception return (BusFault)"),
+    (12, "stacking error on exception entry (BusFault)"),
+    (
+        13,
+        "lazy floating-point state preservation error (BusFault)",
+    ),
+    (16, "undefined instruction"),
+    (17, "invalid state, e.g. no thumb bit"),
+    (18, "invalid pc load on exception return"),
+    (19, "no coprocessor, e.g. the FPU is disabled"),
+    (24, "unaligned access"),
+    (25, "division by zero"),
+];
+
+/// Bits of the HFSR with a description. See Programming Manual Section 4.4.16.
+const HFSR_REASONS: [(u32, &str); 3] = [
+    (1, "vector table read error"),
+    (30, "escalated to HardFault"),
+    (31, "debug event"),
+];
+
+/// Enables the MemManage, BusFault and UsageFault exceptions. Otherwise, these faults
+/// escalate to a HardFault.
+pub fn enable_fault_exceptions() {
+    let shcsr: &mut Register = unsafe { &mut *(SHCSR as *mut Register) };
+    shcsr.set_bits(16, 3);
+}
+
+/// Returns true when the SysTick exception is pending, i.e. the system timer has reached 0
+/// but the exception has not been handled yet. Reads `Bit 26 PENDSTSET` of the ICSR.
+pub fn is_systick_pending() -> bool {
+    let icsr = ICSR as *const u32;
+    unsafe { icsr.read_volatile() & (1 << 26) != 0 }
+}
+
+/// Requests a system reset by setting `Bit 2 SYSRESETREQ` of the AIRCR.
+pub fn system_reset() -> ! {
+    let aircr = AIRCR as *mut u32;
+    unsafe {
+        // The upper half reads as 0xFA05, but has to be written with the key 0x05FA. The
+        // priority grouping in bits 8 to 10 is kept.
+        let prigroup = aircr.read_volatile() & (0b111 << 8);
+        aircr.write_volatile(0x05FA_0000 | prigroup | (1 << 2));
+    }
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Content of the fault status and address registers at the time of a fault.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaultStatus {
+    cfsr: u32,
+    hfsr: u32,
+    mmfar: u32,
+    bfar: u32,
+}
+
+impl FaultStatus {
+    /// Reads the fault status registers and clears them afterwards, so the next fault is
+    /// reported correctly.
+    pub fn take() -> FaultStatus {
+        let cfsr = CFSR as *mut u32;
+        let hfsr = HFSR as *mut u32;
+
+        let status = unsafe {
+            FaultStatus {
+                cfsr: cfsr.read_volatile(),
+                hfsr: hfsr.read_volatile(),
+                mmfar: (MMFAR as *const u32).read_volatile(),
+                bfar: (BFAR as *const u32).read_volatile(),
+            }
+        };
+
+        // The status bits are cleared by writing 1 to them.
+        unsafe {
+            cfsr.write_volatile(status.cfsr);
+            hfsr.write_volatile(status.hfsr);
+        }
+        status
+    }
+
+    pub fn cfsr(&self) -> u32 {
+        self.cfsr
+    }
+
+    pub fn hfsr(&self) -> u32 {
+        self.hfsr
+    }
+
+    /// Address of the data access that caused a memory management fault, if valid.
+    pub fn memory_fault_address(&self) -> Option<u32> {
+        if self.cfsr & MMARVALID != 0 {
+            Some(self.mmfar)
+        } else {
+            None
+        }
+    }
+
+    /// Address of the data access that caused a bus fault, if valid.
+    pub fn bus_fault_address(&self) -> Option<u32> {
+        if self.cfsr & BFARVALID != 0 {
+            Some(self.bfar)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true when the fault occurred while the exception frame got stacked or
+    /// unstacked. The stacked exception frame is not valid in that case.
+    pub fn is_stacking_error(&self) -> bool {
+        self.cfsr & STACKING_ERRORS != 0
+    }
+
+    /// Returns a description for every fault status bit set.
+    pub fn reasons(&self) -> impl Iterator<Item = &'static str> + '_ {
+        let cfsr = CFSR_REASONS
+            .iter()
+            .filter(move |(bit, _)| self.cfsr & (1 << bit) != 0);
+        let hfsr = HFSR_REASONS
+            .iter()
+            .filter(move |(bit, _)| self.hfsr & (1 << bit) != 0);
+        cfsr.chain(hfsr).map(|(_, reason)| *reason)
+    }
+}
@@ -46,24 +46,18 @@ impl SystemTimer {
     /// Sets the reload value
     ///
     /// Reload value can be any value in the range ```0x00000001-0x00FFFFFF```.
-    /// On each clock cycle the value gets incremented by one.
-    /// The effective amount of time may calculated like this:
-    ///
-    /// time_cycle = 1 / clock
-    ///  
-    /// effective_time = register_value * time_cycle
+    /// On each clock cycle the value gets decremented by one. After reaching 0 it is reloaded,
+    /// so the timer fires every `load + 1` clock cycles.
     ///
     /// *Example*
-    /// time_cycle = 1 / 8000000 = 125 (ns)
-    ///
-    /// effective_time = 0x3AB * time_cycle = 939 * 125 (ns) = 117375 (ns)
     ///
-    /// -> Result: Every 117,375 (usec) a interrupt gets fired.
+    /// `Duration::from_millis(1).as_cycles() - 1` fires every millisecond. See
+    /// [Duration][crate::kernel::time::Duration] for the conversion.
     ///
     /// # Arguments
     ///
     /// * `load` - A u32 which represents the count of systicks until
-    /// a interrupt gets fired.
+    ///   a interrupt gets fired.
     ///
     /// # Returns
     /// * `None`
@@ -75,6 +69,11 @@ impl SystemTimer {
         self
     }
 
+    /// Returns the current value, which counts down from the reload value to 0.
+    pub fn current_value(&mut self) -> u32 {
+        self.p.stk_val.read() & STK_RELOAD_MAX
+    }
+
     /// Any write to the register will clear the field to 0 and sets the COUNTFLAG
     /// in STK_CTRL register to 0.
     pub fn clear_val(&mut self) -> &mut SystemTimer {
@@ -97,6 +96,7 @@ impl SystemTimer {
     }
 
     /// Disables the counter.
+    #[allow(dead_code)]
     pub fn disable(&mut self) -> &mut SystemTimer {
         self.p.stk_ctrl.clear_bit(0);
         self
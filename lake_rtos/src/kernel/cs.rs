@@ -8,26 +8,21 @@
 pub(super) static mut CONTEXT_SWITCH: ContextSwitch = ContextSwitch {
     psp_from_addr: 0,
     psp_next_addr: 0,
+    control_npriv_next: 0,
 };
 
-/// Holds addresses required for context switch
+/// Holds addresses required for context switch. The layout is read by `PendSV` in `asm.s`.
+#[repr(C)]
 pub struct ContextSwitch {
     psp_from_addr: u32,
     psp_next_addr: u32,
+    /// Value of `Bit 0 nPRIV` of the CONTROL register for the next process.
+    control_npriv_next: u32,
 }
 
 impl ContextSwitch {
-    /// Retrieves the required psp addresses for loading and saving the new processes.
-    ///
-    /// Will swap psp addresses from and next so that the second context switch will return
-    /// to the previous process. In this implementation it will always go back to pid0, aka
-    /// our scheduler.
-    pub fn get_addr_and_swap(&mut self) -> (u32, u32) {
-        let jump_to = (self.psp_next_addr, self.psp_from_addr);
-        core::mem::swap(&mut self.psp_next_addr, &mut self.psp_from_addr);
-        jump_to
-    }
-
+    /// Sets the psp address the context of the preempted process gets saved to. An address
+    /// of 0 discards the context, e.g. of a terminated process.
     pub fn set_from_addr(&mut self, psp_from_addr: u32) {
         self.psp_from_addr = psp_from_addr;
     }
@@ -35,4 +30,9 @@ impl ContextSwitch {
     pub fn set_next_addr(&mut self, psp_next_addr: u32) {
         self.psp_next_addr = psp_next_addr;
     }
+
+    /// Sets whether the next process runs privileged in thread mode.
+    pub fn set_next_privileged(&mut self, privileged: bool) {
+        self.control_npriv_next = !privileged as u32;
+    }
 }
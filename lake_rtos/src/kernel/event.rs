@@ -0,0 +1,98 @@
+//! # Event flag groups
+//!
+//! An event flag group holds 32 flags, which are set and cleared independently. A process
+//! waits for any or all of the flags given in a mask, e.g. a button pressed or a timeout
+//! elapsed, a sensor ready and a buffer free. It is blocked until the flags are set or its
+//! timeout expires. Setting flags wakes up every process whose condition is met at once.
+//!
+//! A waiting process may clear the flags it waited for on wake-up, so the next event is not
+//! mixed up with the one just handled. The flags are cleared after all processes woken up by
+//! the same set have seen them.
+//!
+//! Interrupt handlers set and clear flags with [set_from_interrupt] and
+//! [clear_from_interrupt], as they can not use supervisor calls. Only processes can wait.
+
+use super::{
+    exceptions::trigger_PendSV,
+    interrupt_free,
+    scheduler::{scheduler_ref, SchedulerError},
+    svc::SysError,
+};
+
+/// Maximum number of event flag groups.
+pub const MAX_EVENT_GROUPS: usize = 8;
+
+/// Identifies an event flag group.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EventGroupId(pub(super) usize);
+
+/// Whether any or all flags of the mask have to be set.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaitMode {
+    Any,
+    All,
+}
+
+/// The flags a process waits for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct EventCondition {
+    pub(super) mask: u32,
+    pub(super) mode: WaitMode,
+    /// Clears the flags of the mask on wake-up.
+    pub(super) clear: bool,
+}
+
+impl EventCondition {
+    pub(super) fn is_met(&self, flags: u32) -> bool {
+        match self.mode {
+            WaitMode::Any => flags & self.mask != 0,
+            WaitMode::All => flags & self.mask == self.mask,
+        }
+    }
+}
+
+/// Sets the flags of the group from an interrupt handler. Processes woken up get scheduled
+/// once the interrupt has returned.
+///
+/// The interrupt has to keep the default priority, so it does not preempt the kernel. The
+/// flags are set with interrupts disabled, so other interrupts can not interleave with it.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::InvalidArgument] if there is no such group or scheduling has not
+///   started yet.
+#[allow(dead_code)]
+pub fn set_from_interrupt(id: EventGroupId, flags: u32) -> Result<(), SysError> {
+    interrupt_free(|| {
+        let scheduler = match unsafe { scheduler_ref() } {
+            Some(scheduler) => scheduler,
+            None => return Err(SchedulerError::NoSuchEventGroup.into()),
+        };
+        if scheduler.set_event_flags(id, flags)? {
+            trigger_PendSV();
+        }
+        Ok(())
+    })
+}
+
+/// Clears the flags of the group from an interrupt handler.
+///
+/// The interrupt has to keep the default priority, so it does not preempt the kernel. Like
+/// [set_from_interrupt], it runs with interrupts disabled.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::InvalidArgument] if there is no such group or scheduling has not
+///   started yet.
+#[allow(dead_code)]
+pub fn clear_from_interrupt(id: EventGroupId, flags: u32) -> Result<(), SysError> {
+    interrupt_free(|| {
+        let scheduler = match unsafe { scheduler_ref() } {
+            Some(scheduler) => scheduler,
+            None => return Err(SchedulerError::NoSuchEventGroup.into()),
+        };
+        scheduler.clear_event_flags(id, flags)?;
+        Ok(())
+    })
+}
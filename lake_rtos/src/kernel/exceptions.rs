@@ -1,21 +1,32 @@
 //! # Exceptions
 
-use crate::util::register::Register;
+use crate::{cp::scb::FaultStatus, util::register::Register};
 
 use super::{
-    __context_switch, __get_r0, cs::CONTEXT_SWITCH, scheduler::SCHEDULER_REF, svc::SvcOrder,
-    SvcRequest, SvcResult,
+    cs::{ContextSwitch, CONTEXT_SWITCH},
+    event::EventCondition,
+    fault::{halt, Fault, FaultException, FaultReport},
+    ipc::MESSAGE_SIZE,
+    queue::QueueId,
+    scheduler::{scheduler_ref, ExceptionFrame, MemoryAccess, Scheduler, SCHEDULER_REF},
+    svc::{SvcRequest, SvcResult, SysError},
+    time::Duration,
 };
+use core::ptr;
 
 #[cfg(feature = "semihosting")]
 use super::{__sys_readc, __sys_write0, __sys_writec};
 
 /// # SysTick exception
 ///
-/// This function will be called when the SysTick exception is triggered.
+/// This function will be called when the SysTick exception is triggered. It advances the
+/// kernel time and ends the time slice of the current process.
 #[no_mangle]
 pub unsafe extern "C" fn SysTick() {
-    trigger_PendSV();
+    if let Some(scheduler) = SCHEDULER_REF.as_mut() {
+        scheduler.tick();
+        trigger_PendSV();
+    }
 }
 
 /// Set PendSV to pending.
@@ -30,44 +41,570 @@ pub fn trigger_PendSV() {
 /// # PendSV exception
 ///
 /// This exception has the lowest priority and therefore will be executed last when
-/// there are nested exceptions.
+/// there are nested exceptions. The scheduler selects the next process, which is then
+/// switched to directly from the preempted one.
+///
+/// The handler `PendSV` itself is written in `asm.s`, as saving and restoring the context
+/// depends on the EXC_RETURN value in the link register. It calls this function first.
+///
+/// # Returns
+///
+/// * A pointer to the [ContextSwitch] when a context switch is prepared.
+/// * A null pointer when the current process keeps running.
+#[allow(non_snake_case)]
 #[no_mangle]
-pub unsafe extern "C" fn PendSV() {
-    let (psp_next_addr, psp_from_addr) = CONTEXT_SWITCH.get_addr_and_swap();
-    __context_switch(psp_next_addr, psp_from_addr);
+pub unsafe extern "C" fn schedule_PendSV() -> *const ContextSwitch {
+    let scheduler = match scheduler_ref() {
+        Some(scheduler) => scheduler,
+        None => return ptr::null(),
+    };
+    let start = scheduler.cycle_count();
+
+    #[cfg(feature = "mpu-stack-guard")]
+    scheduler.unguard_stack();
+    scheduler.check_stack_canaries();
+
+    let switch = scheduler.schedule();
+
+    #[cfg(feature = "mpu-stack-guard")]
+    if let Some(pid) = scheduler.current_pid() {
+        #[cfg(feature = "mpu-isolation")]
+        scheduler.isolate(pid);
+        scheduler.guard_stack(pid);
+    }
+
+    if !switch {
+        return ptr::null();
+    }
+    if let Some(start) = start {
+        scheduler.record_switch_cycles(start);
+    }
+    ptr::addr_of!(CONTEXT_SWITCH)
 }
 
+/// # Fault exceptions
+///
+/// HardFault, MemManage, BusFault and UsageFault are entered in `asm.s`, which passes the
+/// exception frame stacked by the processor, EXC_RETURN and the IPSR.
+///
+/// The fault is reported and handled according to the [FaultPolicy][super::fault::FaultPolicy].
+/// A terminated process is switched out by PendSV right after this exception, without saving
+/// its context.
+#[no_mangle]
+pub unsafe extern "C" fn handle_fault_exception(
+    frame: *const ExceptionFrame,
+    exc_return: u32,
+    ipsr: u32,
+) {
+    let status = FaultStatus::take();
+    let exception = FaultException::from_ipsr(ipsr);
+    // The frame can not be read, if stacking it caused the fault.
+    let frame = if status.is_stacking_error() {
+        None
+    } else {
+        frame.as_ref()
+    };
+
+    let scheduler = match scheduler_ref() {
+        Some(scheduler) => scheduler,
+        None => {
+            let fault = Fault::classify(exception, &status, None);
+            FaultReport {
+                exception,
+                pid: None,
+                fault,
+                status: &status,
+                frame,
+            }
+            .print();
+            halt()
+        }
+    };
+
+    // A process faulted, if the exception returns to thread mode using the psp.
+    let pid = match exc_return & 0b1100 {
+        0b1100 => scheduler.current_pid(),
+        _ => None,
+    };
+    let stack_guard = pid.and_then(|pid| scheduler.stack_guard(pid));
+    let fault = Fault::classify(exception, &status, stack_guard);
+
+    FaultReport {
+        exception,
+        pid,
+        fault,
+        status: &status,
+        frame,
+    }
+    .print();
+
+    match pid {
+        Some(pid) => {
+            scheduler.handle_fault(pid, fault);
+            trigger_PendSV();
+        }
+        None => scheduler.handle_kernel_fault(),
+    }
+}
 
 /// # SVCall exception
-/// 
+///
+/// The handler `SVCall` itself is written in `asm.s` and passes the exception frame stacked by
+/// the caller and EXC_RETURN. The number of the supervisor call is read from the stacked r12,
+/// its arguments from the stacked r0 to r3. The [SvcResult] is written back to the stacked r0
+/// to r2, so the caller finds it in its registers once the exception returns.
+#[allow(non_snake_case)]
 #[no_mangle]
-pub extern "C" fn SVCall() {
-    let mut order: &mut SvcOrder = unsafe { &mut *(__get_r0() as *mut SvcOrder) };
-    match order.request {
+pub unsafe extern "C" fn handle_SVCall(frame: *mut ExceptionFrame, exc_return: u32) {
+    let frame_addr = frame as u32;
+    let frame = &mut *frame;
+    // A process calls from thread mode using the psp. Otherwise the kernel itself calls.
+    let caller = match exc_return & 0b1100 {
+        0b1100 => scheduler_ref().and_then(|scheduler| scheduler.current_pid()),
+        _ => None,
+    };
+
+    let args = [frame.r0, frame.r1, frame.r2, frame.r3];
+    let result = match SvcRequest::decode(frame.r12, args) {
+        Some(request) => execute_request(request, caller, frame_addr),
+        None => SvcResult::Error(SysError::InvalidArgument),
+    };
+
+    let [r0, r1, r2] = result.encode();
+    frame.r0 = r0;
+    frame.r1 = r1;
+    frame.r2 = r2;
+}
+
+/// Executes the request on behalf of the caller, which is [None] for the kernel itself.
+///
+/// A request blocking the caller returns [SvcResult::None] for now. The actual result is
+/// written to the exception `frame` once the caller gets woken up.
+unsafe fn execute_request(request: SvcRequest, caller: Option<usize>, frame: u32) -> SvcResult {
+    let scheduler = match scheduler_ref() {
+        Some(scheduler) => scheduler,
+        None => return execute_kernel_request(request),
+    };
+
+    match request {
         #[cfg(feature = "semihosting")]
         SvcRequest::SemihostingWrite0(text) => {
-            unsafe { __sys_write0(text) }
-            order.response = SvcResult::None;
+            if !is_readable_c_str(scheduler, caller, text) {
+                return SvcResult::Error(SysError::PermissionDenied);
+            }
+            __sys_write0(text);
+            SvcResult::None
         }
         #[cfg(feature = "semihosting")]
         SvcRequest::SemihostingWriteC(char) => {
-            unsafe { __sys_writec(char) }
-            order.response = SvcResult::None;
+            if !is_accessible(scheduler, caller, char as u32, 1, MemoryAccess::Read) {
+                return SvcResult::Error(SysError::PermissionDenied);
+            }
+            __sys_writec(char);
+            SvcResult::None
         }
         #[cfg(feature = "semihosting")]
-        SvcRequest::SemihostingReadC => unsafe {
-            order.response = SvcResult::Char(__sys_readc());
-        },
+        SvcRequest::SemihostingReadC => SvcResult::Char(__sys_readc()),
         SvcRequest::Yield => {
-            let scheduler = unsafe { SCHEDULER_REF.as_mut().unwrap() };
-            scheduler.disable_timed_context_switch();
+            scheduler.finish_current_job();
 
             #[cfg(feature = "semihosting")]
-            unsafe {
-                __sys_write0("yield\n\0".as_bytes().as_ptr() as *const u8)
-            };
+            __sys_write0("yield\n\0".as_bytes().as_ptr() as *const u8);
 
             trigger_PendSV();
+            SvcResult::None
+        }
+        SvcRequest::Sleep(ticks) => {
+            if scheduler.sleep_current_until(scheduler.ticks() + ticks as u64) {
+                trigger_PendSV();
+            }
+            SvcResult::None
+        }
+        SvcRequest::SleepUntil(tick) => {
+            if scheduler.sleep_current_until(tick) {
+                trigger_PendSV();
+            }
+            SvcResult::None
+        }
+        SvcRequest::GetTime => SvcResult::Time(scheduler.now()),
+        SvcRequest::Exit(exit_code) => {
+            scheduler.exit_current(exit_code);
+            trigger_PendSV();
+            SvcResult::None
+        }
+        SvcRequest::Kill(0) => SvcResult::Error(SysError::PermissionDenied),
+        SvcRequest::Kill(pid) if !may_target(scheduler, caller, pid) => {
+            SvcResult::Error(SysError::PermissionDenied)
+        }
+        SvcRequest::Kill(pid) => match scheduler.kill(pid) {
+            Ok(()) => {
+                if scheduler.current_pid() == Some(pid) {
+                    trigger_PendSV();
+                }
+                SvcResult::None
+            }
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::StackUsage(pid) if !may_target(scheduler, caller, pid) => {
+            SvcResult::Error(SysError::PermissionDenied)
+        }
+        SvcRequest::StackUsage(pid) => match scheduler.stack_usage(pid) {
+            Ok(bytes) => SvcResult::Bytes(bytes),
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::Spawn {
+            entry,
+            stack_size,
+            priority,
+        } => {
+            // The thumb bit is not part of the address.
+            let entry_addr = entry as usize as u32 & !1;
+            if !is_privileged(scheduler, caller)
+                || !is_accessible(scheduler, caller, entry_addr, 2, MemoryAccess::Execute)
+            {
+                return SvcResult::Error(SysError::PermissionDenied);
+            }
+            match scheduler.spawn(entry, stack_size, priority) {
+                Ok(pid) => SvcResult::Pid(pid),
+                Err(error) => SvcResult::Error(error.into()),
+            }
+        }
+        SvcRequest::TimerCreate { duration, periodic } => {
+            match scheduler.create_timer_for_current(duration, periodic) {
+                Ok(id) => SvcResult::Id(id.0),
+                Err(error) => SvcResult::Error(error.into()),
+            }
+        }
+        SvcRequest::TimerWait(id) => match scheduler.wait_timer_current(id, frame) {
+            Ok(blocked) => {
+                if blocked {
+                    trigger_PendSV();
+                }
+                SvcResult::None
+            }
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::TimerCancel(id) => match scheduler.cancel_timer_for_current(id) {
+            Ok(()) => SvcResult::None,
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::SemCreate { initial, max } => match scheduler.create_semaphore(initial, max) {
+            Ok(id) => SvcResult::Id(id.0),
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::SemWait { id, timeout } => {
+            match scheduler.wait_semaphore_current(id, timeout, frame) {
+                Ok(blocked) => {
+                    if blocked {
+                        trigger_PendSV();
+                    }
+                    SvcResult::None
+                }
+                Err(error) => SvcResult::Error(error.into()),
+            }
+        }
+        SvcRequest::SemTryWait(id) => {
+            let timeout = Some(Duration::from_cycles(0));
+            match scheduler.wait_semaphore_current(id, timeout, frame) {
+                Ok(_) => SvcResult::None,
+                Err(error) => SvcResult::Error(error.into()),
+            }
+        }
+        SvcRequest::SemPost(id) => match scheduler.post_semaphore(id) {
+            Ok(woken) => {
+                if woken {
+                    trigger_PendSV();
+                }
+                SvcResult::None
+            }
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::MutexCreate { recursive } => match scheduler.create_mutex(recursive) {
+            Ok(id) => SvcResult::Id(id.0),
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::MutexLock { id, timeout } => {
+            match scheduler.lock_mutex_current(id, timeout, frame) {
+                Ok(blocked) => {
+                    if blocked {
+                        trigger_PendSV();
+                    }
+                    SvcResult::None
+                }
+                Err(error) => SvcResult::Error(error.into()),
+            }
+        }
+        SvcRequest::MutexTryLock(id) => {
+            let timeout = Some(Duration::from_cycles(0));
+            match scheduler.lock_mutex_current(id, timeout, frame) {
+                Ok(_) => SvcResult::None,
+                Err(error) => SvcResult::Error(error.into()),
+            }
+        }
+        SvcRequest::QueueCreate {
+            message_size,
+            capacity,
+        } => match scheduler.create_queue(message_size as usize, capacity as usize) {
+            Ok(id) => SvcResult::Id(id.0),
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::QueueSend {
+            id,
+            message,
+            timeout,
+        } => send_queue(scheduler, caller, id, message, timeout, frame),
+        SvcRequest::QueueTrySend { id, message } => {
+            let timeout = Some(Duration::from_cycles(0));
+            send_queue(scheduler, caller, id, message, timeout, frame)
+        }
+        SvcRequest::QueueReceive {
+            id,
+            buffer,
+            timeout,
+        } => receive_queue(scheduler, caller, id, buffer, timeout, frame),
+        SvcRequest::QueueTryReceive { id, buffer } => {
+            let timeout = Some(Duration::from_cycles(0));
+            receive_queue(scheduler, caller, id, buffer, timeout, frame)
+        }
+        SvcRequest::EventCreate => match scheduler.create_event_group() {
+            Ok(id) => SvcResult::Id(id.0),
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::EventSet { id, flags } => match scheduler.set_event_flags(id, flags) {
+            Ok(woken) => {
+                if woken {
+                    trigger_PendSV();
+                }
+                SvcResult::None
+            }
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::EventClear { id, flags } => match scheduler.clear_event_flags(id, flags) {
+            Ok(()) => SvcResult::None,
+            Err(error) => SvcResult::Error(error.into()),
+        },
+        SvcRequest::EventWait {
+            id,
+            mask,
+            mode,
+            clear,
+            timeout,
+        } => {
+            let condition = EventCondition { mask, mode, clear };
+            match scheduler.wait_event_flags_current(id, condition, timeout, frame) {
+                Ok(Some(flags)) => SvcResult::Flags(flags),
+                Ok(None) => {
+                    trigger_PendSV();
+                    SvcResult::None
+                }
+                Err(error) => SvcResult::Error(error.into()),
+            }
+        }
+        SvcRequest::IpcSend {
+            pid,
+            message,
+            reply,
+        } => {
+            if !is_accessible(
+                scheduler,
+                caller,
+                message as u32,
+                MESSAGE_SIZE,
+                MemoryAccess::Read,
+            ) || !is_accessible(
+                scheduler,
+                caller,
+                reply as u32,
+                MESSAGE_SIZE,
+                MemoryAccess::Write,
+            ) {
+                return SvcResult::Error(SysError::PermissionDenied);
+            }
+            match scheduler.send_current(pid, message as u32, reply as u32, frame) {
+                Ok(()) => {
+                    trigger_PendSV();
+                    SvcResult::None
+                }
+                Err(error) => SvcResult::Error(error.into()),
+            }
+        }
+        SvcRequest::IpcReceive(buffer) => {
+            if !is_accessible(
+                scheduler,
+                caller,
+                buffer as u32,
+                MESSAGE_SIZE,
+                MemoryAccess::Write,
+            ) {
+                return SvcResult::Error(SysError::PermissionDenied);
+            }
+            match scheduler.receive_current(buffer as u32, frame) {
+                Ok(Some(sender)) => SvcResult::Pid(sender),
+                Ok(None) => {
+                    trigger_PendSV();
+                    SvcResult::None
+                }
+                Err(error) => SvcResult::Error(error.into()),
+            }
+        }
+        SvcRequest::IpcReply { pid, message } => {
+            if !is_accessible(
+                scheduler,
+                caller,
+                message as u32,
+                MESSAGE_SIZE,
+                MemoryAccess::Read,
+            ) {
+                return SvcResult::Error(SysError::PermissionDenied);
+            }
+            match scheduler.reply_current(pid, message as u32) {
+                Ok(()) => {
+                    trigger_PendSV();
+                    SvcResult::None
+                }
+                Err(error) => SvcResult::Error(error.into()),
+            }
+        }
+        SvcRequest::MutexUnlock(id) => match scheduler.unlock_mutex_current(id) {
+            // The woken process might preempt the caller, which has lost an inherited priority.
+            Ok(woken) => {
+                if woken {
+                    trigger_PendSV();
+                }
+                SvcResult::None
+            }
+            Err(error) => SvcResult::Error(error.into()),
+        },
+    }
+}
+
+/// Sends the message of the caller, which has to be readable by it.
+fn send_queue(
+    scheduler: &mut Scheduler,
+    caller: Option<usize>,
+    id: QueueId,
+    message: *const u8,
+    timeout: Option<Duration>,
+    frame: u32,
+) -> SvcResult {
+    let message_size = match scheduler.queue_message_size(id) {
+        Ok(message_size) => message_size,
+        Err(error) => return SvcResult::Error(error.into()),
+    };
+    if !is_accessible(
+        scheduler,
+        caller,
+        message as u32,
+        message_size,
+        MemoryAccess::Read,
+    ) {
+        return SvcResult::Error(SysError::PermissionDenied);
+    }
+    match scheduler.send_queue_current(id, message as u32, timeout, frame) {
+        Ok(switch) => {
+            if switch {
+                trigger_PendSV();
+            }
+            SvcResult::None
+        }
+        Err(error) => SvcResult::Error(error.into()),
+    }
+}
+
+/// Receives a message into the buffer of the caller, which has to be writable by it.
+fn receive_queue(
+    scheduler: &mut Scheduler,
+    caller: Option<usize>,
+    id: QueueId,
+    buffer: *mut u8,
+    timeout: Option<Duration>,
+    frame: u32,
+) -> SvcResult {
+    let message_size = match scheduler.queue_message_size(id) {
+        Ok(message_size) => message_size,
+        Err(error) => return SvcResult::Error(error.into()),
+    };
+    if !is_accessible(
+        scheduler,
+        caller,
+        buffer as u32,
+        message_size,
+        MemoryAccess::Write,
+    ) {
+        return SvcResult::Error(SysError::PermissionDenied);
+    }
+    match scheduler.receive_queue_current(id, buffer as u32, timeout, frame) {
+        Ok(switch) => {
+            if switch {
+                trigger_PendSV();
+            }
+            SvcResult::None
+        }
+        Err(error) => SvcResult::Error(error.into()),
+    }
+}
+
+/// Executes a request of the kernel before scheduling started. Only the semihosting requests
+/// are available.
+unsafe fn execute_kernel_request(request: SvcRequest) -> SvcResult {
+    match request {
+        #[cfg(feature = "semihosting")]
+        SvcRequest::SemihostingWrite0(text) => {
+            __sys_write0(text);
+            SvcResult::None
+        }
+        #[cfg(feature = "semihosting")]
+        SvcRequest::SemihostingWriteC(char) => {
+            __sys_writec(char);
+            SvcResult::None
+        }
+        #[cfg(feature = "semihosting")]
+        SvcRequest::SemihostingReadC => SvcResult::Char(__sys_readc()),
+        _ => SvcResult::Error(SysError::InvalidArgument),
+    }
+}
+
+/// Returns true when the caller runs privileged. The kernel always does.
+fn is_privileged(scheduler: &Scheduler, caller: Option<usize>) -> bool {
+    match caller {
+        Some(pid) => scheduler.is_privileged(pid),
+        None => true,
+    }
+}
+
+/// Returns true when the caller may kill or inspect the process `pid`. An unprivileged
+/// process may only target itself.
+fn may_target(scheduler: &Scheduler, caller: Option<usize>, pid: usize) -> bool {
+    caller == Some(pid) || is_privileged(scheduler, caller)
+}
+
+/// Returns true when the caller may access `len` bytes at `addr`. The kernel may access
+/// any memory.
+fn is_accessible(
+    scheduler: &Scheduler,
+    caller: Option<usize>,
+    addr: u32,
+    len: u32,
+    access: MemoryAccess,
+) -> bool {
+    match caller {
+        Some(pid) => scheduler.is_accessible(pid, addr, len, access),
+        None => true,
+    }
+}
+
+/// Returns true when the caller may read the null-terminated string at `text` including its
+/// terminating null. The kernel may read any memory.
+#[cfg(feature = "semihosting")]
+fn is_readable_c_str(scheduler: &Scheduler, caller: Option<usize>, text: *const u8) -> bool {
+    let pid = match caller {
+        Some(pid) => pid,
+        None => return true,
+    };
+    match scheduler.accessible_range(pid, text as u32, MemoryAccess::Read) {
+        Some(range) => {
+            (text as u32..range.end).any(|addr| unsafe { (addr as *const u8).read_volatile() } == 0)
         }
+        None => false,
     }
 }
@@ -0,0 +1,179 @@
+//! # Faults
+//!
+//! Faults detected by the kernel are reported to the [FaultHook] set with
+//! [set_fault_hook][super::scheduler::Scheduler::set_fault_hook]. Afterwards the
+//! [FaultPolicy] decides whether the system halts, resets or only the faulting process
+//! gets terminated. The other processes keep running then and the faulting process may be
+//! restarted according to its [RestartPolicy].
+//!
+//! The fault exceptions HardFault, MemManage, BusFault and UsageFault additionally print a
+//! [FaultReport] with the stacked registers and the decoded fault status to the sink set with
+//! [set_panic_sink][lake_rtos_rt::panic::set_panic_sink], e.g. over semihosting.
+
+use core::{fmt, ops::Range};
+
+use super::scheduler::ExceptionFrame;
+use crate::cp::scb::FaultStatus;
+use lake_rtos_rt::panic::{panic_sink, SinkWriter};
+
+/// Faults a process can cause.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// The process has overflown its stack.
+    StackOverflow,
+    /// The process accessed memory it has no access to. Holds the faulting address, if the
+    /// processor could determine it.
+    MemoryViolation(Option<u32>),
+    /// A bus error occurred on a memory access. Holds the faulting address, if the processor
+    /// could determine it.
+    BusError(Option<u32>),
+    /// An instruction could not be executed, e.g. an undefined instruction or a division
+    /// by zero.
+    UsageError,
+    /// A fault that escalated to a HardFault or could not be handled otherwise.
+    Escalated,
+}
+
+/// Gets called with the pid of the faulting process and the detected [Fault].
+///
+/// The hook runs within an exception. Therefore, it must not use system calls.
+pub type FaultHook = fn(pid: usize, fault: Fault);
+
+/// Decides what happens after a fault was reported.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultPolicy {
+    /// Halts the whole system.
+    Halt,
+    /// Resets the system.
+    Reset,
+    /// Terminates the faulting process only. A fault of the kernel or the idle task (pid0)
+    /// halts the system.
+    Terminate,
+}
+
+/// Decides whether a process is restarted after it got terminated by a fault. See
+/// [set_restart_policy][super::scheduler::Scheduler::set_restart_policy].
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestartPolicy {
+    /// The process stays terminated.
+    Never,
+    /// The process is restarted after every fault.
+    Always,
+    /// The process is restarted after a fault, until it has been restarted the given number
+    /// of times.
+    UpTo(u32),
+}
+
+/// The exceptions raised by faults, numbered like the IPSR.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum FaultException {
+    HardFault = 3,
+    MemManage = 4,
+    BusFault = 5,
+    UsageFault = 6,
+}
+
+impl FaultException {
+    /// Returns the exception for the exception number read from the IPSR.
+    pub(super) fn from_ipsr(ipsr: u32) -> FaultException {
+        match ipsr & 0x1FF {
+            4 => FaultException::MemManage,
+            5 => FaultException::BusFault,
+            6 => FaultException::UsageFault,
+            _ => FaultException::HardFault,
+        }
+    }
+}
+
+impl Fault {
+    /// Determines the fault from the exception and the fault status. A memory access within
+    /// `stack_guard`, as well as a stacking error, is considered a [Fault::StackOverflow].
+    pub(super) fn classify(
+        exception: FaultException,
+        status: &FaultStatus,
+        stack_guard: Option<Range<u32>>,
+    ) -> Fault {
+        match exception {
+            FaultException::MemManage => match status.memory_fault_address() {
+                Some(address) if stack_guard.is_some_and(|guard| guard.contains(&address)) => {
+                    Fault::StackOverflow
+                }
+                _ if status.is_stacking_error() => Fault::StackOverflow,
+                address => Fault::MemoryViolation(address),
+            },
+            FaultException::BusFault => Fault::BusError(status.bus_fault_address()),
+            FaultException::UsageFault => Fault::UsageError,
+            FaultException::HardFault => Fault::Escalated,
+        }
+    }
+}
+
+/// Human-readable report of a fault exception.
+pub(super) struct FaultReport<'a> {
+    pub(super) exception: FaultException,
+    /// [None] when the kernel faulted.
+    pub(super) pid: Option<usize>,
+    pub(super) fault: Fault,
+    pub(super) status: &'a FaultStatus,
+    /// [None] when the exception frame could not be stacked.
+    pub(super) frame: Option<&'a ExceptionFrame>,
+}
+
+impl FaultReport<'_> {
+    /// Prints the report to the panic sink. Without a sink, the report is dropped.
+    pub(super) fn print(&self) {
+        if let Some(sink) = panic_sink() {
+            use fmt::Write;
+            let _ = write!(SinkWriter(sink), "{}", self);
+        }
+    }
+}
+
+impl fmt::Display for FaultReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pid {
+            Some(pid) => writeln!(f, "{:?} in pid {}: {:?}", self.exception, pid, self.fault)?,
+            None => writeln!(f, "{:?} in kernel: {:?}", self.exception, self.fault)?,
+        }
+        for reason in self.status.reasons() {
+            writeln!(f, "  reason: {}", reason)?;
+        }
+        writeln!(
+            f,
+            "  cfsr 0x{:08x} hfsr 0x{:08x}",
+            self.status.cfsr(),
+            self.status.hfsr()
+        )?;
+        if let Some(address) = self.status.memory_fault_address() {
+            writeln!(f, "  mmfar 0x{:08x}", address)?;
+        }
+        if let Some(address) = self.status.bus_fault_address() {
+            writeln!(f, "  bfar 0x{:08x}", address)?;
+        }
+        match self.frame {
+            Some(frame) => {
+                writeln!(
+                    f,
+                    "  r0 0x{:08x} r1 0x{:08x} r2 0x{:08x} r3 0x{:08x} r12 0x{:08x}",
+                    frame.r0, frame.r1, frame.r2, frame.r3, frame.r12
+                )?;
+                writeln!(
+                    f,
+                    "  pc 0x{:08x} lr 0x{:08x} xpsr 0x{:08x}",
+                    frame.pc, frame.lr, frame.xpsr
+                )
+            }
+            None => writeln!(f, "  exception frame not available"),
+        }
+    }
+}
+
+/// Stops the system.
+pub(super) fn halt() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,33 @@
+//! # Synchronous message passing
+//!
+//! Processes exchange a [Message] in a rendezvous, similar to L4 or QNX. A client sends a
+//! message to a server process and stays blocked until the server has received the message
+//! and replied to it. The kernel copies the message and the reply between the stacks of both
+//! processes, without any buffering.
+//!
+//! ```text
+//!  client                    server
+//!    |                         | receive
+//!    | send ---- message ----> |
+//!    |                         | handles the request
+//!    | <------- reply -------- | reply
+//!    |                         | receive
+//! ```
+//!
+//! A client sending to a server that waits in receive switches directly to the server, which
+//! runs next in place of the client, unless the policy ranks another ready process higher.
+//! Likewise, the reply switches directly back to the client.
+//! This lets a driver, e.g. the LEDs, live in a server process that owns the peripheral,
+//! while clients only talk to it through messages.
+//!
+//! If the server terminates, its blocked clients return with
+//! [NoSuchProcess][super::svc::SysError::NoSuchProcess].
+
+/// Number of words of a [Message].
+pub const MESSAGE_WORDS: usize = 4;
+
+/// Message and reply exchanged between processes.
+pub type Message = [u32; MESSAGE_WORDS];
+
+/// Size of a [Message] in bytes.
+pub(super) const MESSAGE_SIZE: u32 = (MESSAGE_WORDS * 4) as u32;
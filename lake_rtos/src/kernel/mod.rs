@@ -1,27 +1,30 @@
 //! # Kernel
 //!
 
-#[cfg(feature = "semihosting")]
-pub use self::svc::sprint;
-pub use self::svc::{syscall, SvcRequest, SvcResult};
-
+pub mod event;
+pub mod fault;
+pub mod ipc;
+pub mod mutex;
+pub mod queue;
 pub mod scheduler;
+pub mod semaphore;
+pub mod svc;
+pub mod time;
+pub mod timer;
 
 mod cs;
 mod exceptions;
-mod svc;
 
 extern "C" {
-    /// The context switch only works when called within an interrupt. e.g. [exceptions::PendSV]
-    fn __context_switch(psp_next_addr: u32, psp_from_addr: u32);
     /// Sets a breakpoint in the running program.
     fn __breakpoint();
     /// Triggers the supervisor call.
     ///
     /// # Argument
     ///
-    /// * A pointer to an supervisor call order.
-    fn __syscall(order: *const svc::SvcOrder);
+    /// * A pointer to the registers holding the arguments and the number of the supervisor
+    ///   call. The result written back by the kernel is stored there afterwards.
+    fn __syscall(registers: *mut svc::SvcRegisters);
     /// # ARM Semihosting SYS_WRITE0
     ///
     /// Writes a null-terminated string to the debug channel.
@@ -46,6 +49,21 @@ extern "C" {
     ///
     /// * The byte read from the console.
     fn __sys_readc() -> u8;
-    /// Returns value from register 0.
-    fn __get_r0() -> *mut u32;
+    /// Masks all exceptions with configurable priority by setting PRIMASK.
+    ///
+    /// # Return
+    ///
+    /// * The previous value of PRIMASK.
+    fn __disable_interrupts() -> u32;
+    /// Restores PRIMASK to a value returned by `__disable_interrupts`.
+    fn __restore_interrupts(primask: u32);
+}
+
+/// Runs `f` with interrupts disabled, so neither an interrupt nor the kernel can preempt it.
+/// Nested calls keep interrupts disabled until the outermost returns.
+fn interrupt_free<R>(f: impl FnOnce() -> R) -> R {
+    let primask = unsafe { __disable_interrupts() };
+    let result = f();
+    unsafe { __restore_interrupts(primask) };
+    result
 }
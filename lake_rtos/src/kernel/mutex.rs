@@ -0,0 +1,58 @@
+//! # Mutexes
+//!
+//! A mutex is owned by the process that locked it, until that process unlocks it again. Only
+//! the owner may unlock it. A recursive mutex may be locked again by its owner and has to be
+//! unlocked as often as it was locked.
+//!
+//! A process locking a mutex owned by another process is blocked and put into the wait queue
+//! of the mutex. Unlocking hands the mutex directly to the waiting process with the highest
+//! priority, first come, first served among equal priorities.
+//!
+//! ## Priority inheritance
+//!
+//! While a process with a higher priority waits for a mutex, the owner inherits that priority.
+//! Otherwise a process with a medium priority could preempt the owner for an unbounded time
+//! and thereby delay the waiting process, which is called priority inversion. The inheritance
+//! passes along a chain of owners that wait for mutexes themselves. The owner falls back to
+//! its own priority, once no process with a higher priority waits for one of its mutexes.
+//!
+//! Mutexes are only available to processes. Interrupt handlers can not own a mutex.
+
+/// Maximum number of mutexes.
+pub const MAX_MUTEXES: usize = 8;
+
+/// Identifies a mutex.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MutexId(pub(super) usize);
+
+impl MutexId {
+    /// Bit of the mutex in the mutexes held by a process.
+    pub(super) fn mask(&self) -> u32 {
+        1 << self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Mutex {
+    /// Process holding the mutex.
+    pub(super) owner: Option<usize>,
+    /// How often the owner has locked the mutex.
+    pub(super) lock_count: u32,
+    pub(super) recursive: bool,
+}
+
+impl Mutex {
+    pub(super) fn new(recursive: bool) -> Mutex {
+        Mutex {
+            owner: None,
+            lock_count: 0,
+            recursive,
+        }
+    }
+
+    /// Makes the process the owner, which has locked the mutex once.
+    pub(super) fn hand_to(&mut self, pid: usize) {
+        self.owner = Some(pid);
+        self.lock_count = 1;
+    }
+}
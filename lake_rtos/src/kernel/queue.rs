@@ -0,0 +1,281 @@
+//! # Message queues
+//!
+//! A message queue passes messages of a fixed size between processes, first in, first out.
+//! The kernel copies each message from the stack of the sender into the queue and from the
+//! queue onto the stack of the receiver, so the processes do not share any memory.
+//!
+//! A process receiving from an empty queue is blocked until a message is sent. The message is
+//! then copied directly onto its stack. A process sending to a full queue is blocked until a
+//! message is received and its message fits into the queue. Both wait queues are ordered by
+//! priority, first come, first served among equal priorities.
+//!
+//! The messages of all queues are kept in one buffer of [QUEUE_BUFFER_SIZE] bytes owned by
+//! the kernel. Each queue takes its capacity times the message size of it on creation.
+//!
+//! [MessageQueue] is the typed handle processes use.
+
+use super::{
+    scheduler::{Scheduler, SchedulerError},
+    svc::{syscall, SvcRequest, SvcResult, SysError},
+    time::Duration,
+};
+use core::{
+    marker::PhantomData,
+    mem::{size_of, MaybeUninit},
+    ptr,
+};
+
+/// Maximum number of message queues.
+pub const MAX_QUEUES: usize = 4;
+
+/// Size in bytes of the buffer holding the messages of all queues.
+pub const QUEUE_BUFFER_SIZE: usize = 512;
+
+/// Only accessed by the kernel.
+static mut QUEUE_BUFFER: [u8; QUEUE_BUFFER_SIZE] = [0; QUEUE_BUFFER_SIZE];
+
+/// Identifies a message queue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueueId(pub(super) usize);
+
+/// A ring of `capacity` slots of `message_size` bytes within the [QUEUE_BUFFER].
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Queue {
+    /// Offset of the first slot within the [QUEUE_BUFFER].
+    start: usize,
+    message_size: usize,
+    capacity: usize,
+    /// Slot of the oldest message.
+    head: usize,
+    len: usize,
+}
+
+impl Queue {
+    pub(super) fn message_size(&self) -> usize {
+        self.message_size
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(super) fn is_full(&self) -> bool {
+        self.len == self.capacity
+    }
+
+    /// Copies the message at `message` behind the newest message. A full queue is left as is.
+    ///
+    /// # Safety
+    ///
+    /// `message` has to point to [message_size][Queue::message_size] readable bytes.
+    pub(super) unsafe fn push(&mut self, message: *const u8) {
+        if self.is_full() {
+            return;
+        }
+        let slot = (self.head + self.len) % self.capacity;
+        ptr::copy_nonoverlapping(message, self.slot(slot), self.message_size);
+        self.len += 1;
+    }
+
+    /// Moves the oldest message to `destination`. An empty queue is left as is.
+    ///
+    /// # Safety
+    ///
+    /// `destination` has to point to [message_size][Queue::message_size] writable bytes.
+    pub(super) unsafe fn pop(&mut self, destination: *mut u8) {
+        if self.is_empty() {
+            return;
+        }
+        ptr::copy_nonoverlapping(self.slot(self.head), destination, self.message_size);
+        self.head = (self.head + 1) % self.capacity;
+        self.len -= 1;
+    }
+
+    unsafe fn slot(&self, slot: usize) -> *mut u8 {
+        (ptr::addr_of_mut!(QUEUE_BUFFER) as *mut u8).add(self.start + slot * self.message_size)
+    }
+}
+
+/// The message queues and the part of the [QUEUE_BUFFER] allocated for them.
+#[derive(Debug)]
+pub(super) struct QueueList {
+    queues: [Option<Queue>; MAX_QUEUES],
+    buffer_used: usize,
+}
+
+impl QueueList {
+    pub(super) const fn new() -> QueueList {
+        QueueList {
+            queues: [None; MAX_QUEUES],
+            buffer_used: 0,
+        }
+    }
+
+    /// Creates an empty queue for `capacity` messages of `message_size` bytes.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SchedulerError::InvalidCount] if `capacity` or `message_size` is zero.
+    /// * [Err] with [SchedulerError::QueuesExhausted] if all queues are in use or the
+    ///   [QUEUE_BUFFER] has not enough space left.
+    pub(super) fn create(
+        &mut self,
+        message_size: usize,
+        capacity: usize,
+    ) -> Result<QueueId, SchedulerError> {
+        if message_size == 0 || capacity == 0 {
+            return Err(SchedulerError::InvalidCount);
+        }
+        let size = message_size
+            .checked_mul(capacity)
+            .ok_or(SchedulerError::QueuesExhausted)?;
+        if size > QUEUE_BUFFER_SIZE - self.buffer_used {
+            return Err(SchedulerError::QueuesExhausted);
+        }
+        let index = self
+            .queues
+            .iter()
+            .position(|slot| slot.is_none())
+            .ok_or(SchedulerError::QueuesExhausted)?;
+
+        self.queues[index] = Some(Queue {
+            start: self.buffer_used,
+            message_size,
+            capacity,
+            head: 0,
+            len: 0,
+        });
+        self.buffer_used += size;
+        Ok(QueueId(index))
+    }
+
+    pub(super) fn get_mut(&mut self, id: QueueId) -> Result<&mut Queue, SchedulerError> {
+        match self.queues.get_mut(id.0) {
+            Some(Some(queue)) => Ok(queue),
+            _ => Err(SchedulerError::NoSuchQueue),
+        }
+    }
+}
+
+/// Handle of a message queue holding up to `N` messages of type `T`.
+///
+/// The handle can be copied and passed to other processes, e.g. with
+/// [create_process_with_ref][Scheduler::create_process_with_ref].
+#[derive(Debug)]
+pub struct MessageQueue<T: Copy, const N: usize> {
+    id: QueueId,
+    message: PhantomData<T>,
+}
+
+impl<T: Copy, const N: usize> Clone for MessageQueue<T, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Copy, const N: usize> Copy for MessageQueue<T, N> {}
+
+#[allow(dead_code)]
+impl<T: Copy, const N: usize> MessageQueue<T, N> {
+    /// Creates the queue before scheduling starts.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SchedulerError::InvalidCount] if `N` is zero or `T` has no size.
+    /// * [Err] with [SchedulerError::QueuesExhausted] if all queues are in use or there is not
+    ///   enough space left for `N` messages.
+    pub fn new(scheduler: &mut Scheduler) -> Result<Self, SchedulerError> {
+        let id = scheduler.create_queue(size_of::<T>(), N)?;
+        Ok(MessageQueue {
+            id,
+            message: PhantomData,
+        })
+    }
+
+    /// Creates the queue from a process.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SysError::InvalidArgument] if `N` is zero or `T` has no size.
+    /// * [Err] with [SysError::OutOfResources] if all queues are in use or there is not enough
+    ///   space left for `N` messages.
+    pub fn create() -> Result<Self, SysError> {
+        let request = SvcRequest::QueueCreate {
+            message_size: size_of::<T>() as u32,
+            capacity: N as u32,
+        };
+        match syscall(request) {
+            SvcResult::Id(id) => Ok(MessageQueue {
+                id: QueueId(id),
+                message: PhantomData,
+            }),
+            other => Err(other.into_error()),
+        }
+    }
+
+    pub fn id(&self) -> QueueId {
+        self.id
+    }
+
+    /// Sends a copy of the message. Blocks the calling process while the queue is full, or at
+    /// most for `timeout` if given.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SysError::Timeout] if the queue stayed full within the timeout.
+    pub fn send(&self, message: &T, timeout: Option<Duration>) -> Result<(), SysError> {
+        syscall(SvcRequest::QueueSend {
+            id: self.id,
+            message: message as *const T as *const u8,
+            timeout,
+        })
+        .into_unit()
+    }
+
+    /// Sends a copy of the message, if the queue is not full.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SysError::WouldBlock] if the queue is full.
+    pub fn try_send(&self, message: &T) -> Result<(), SysError> {
+        syscall(SvcRequest::QueueTrySend {
+            id: self.id,
+            message: message as *const T as *const u8,
+        })
+        .into_unit()
+    }
+
+    /// Receives the oldest message. Blocks the calling process while the queue is empty, or at
+    /// most for `timeout` if given.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SysError::Timeout] if no message was sent within the timeout.
+    pub fn recv(&self, timeout: Option<Duration>) -> Result<T, SysError> {
+        let mut message = MaybeUninit::<T>::uninit();
+        syscall(SvcRequest::QueueReceive {
+            id: self.id,
+            buffer: message.as_mut_ptr() as *mut u8,
+            timeout,
+        })
+        .into_unit()?;
+        // The kernel has copied a message of the size of T, which was sent as T.
+        Ok(unsafe { message.assume_init() })
+    }
+
+    /// Receives the oldest message, if the queue is not empty.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SysError::WouldBlock] if the queue is empty.
+    pub fn try_recv(&self) -> Result<T, SysError> {
+        let mut message = MaybeUninit::<T>::uninit();
+        syscall(SvcRequest::QueueTryReceive {
+            id: self.id,
+            buffer: message.as_mut_ptr() as *mut u8,
+        })
+        .into_unit()?;
+        // See recv.
+        Ok(unsafe { message.assume_init() })
+    }
+}
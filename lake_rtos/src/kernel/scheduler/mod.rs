@@ -1,23 +1,23 @@
 //! # Scheduler
 //!
-//! Processes will be placed top of SRAM
+//! Processes will be placed in the stack region defined in `link.x` of `lake_rtos_rt`
 //!
 //! ```text
 //!     Memory model
-//!   |     ...      |  
+//!   |     ...      |
 //!   |  Peripheral  |
 //!   |--------------| 0x4000_0000
 //!   |     ...      |
 //!   |--------------| 0x2000 9C40 (upper limit for our discovery board version)
 //!   |              | <<<< msp <<<< | start main stack |
 //!   |              |
-//! | |              |
-//! | |              | <<<< psp <<<< | Process stack 0 (pid0) | 0x2000_6000
-//! | |     SRAM     |               | Process stack 1 (pid1) | 0x2000_5000
-//! v*|              |               | Process stack 2 (pid2) | 0x2000_4000
-//!   |              |               | Process stack 3 (pid3) | 0x2000_3000
-//!   |              |               | ...
 //!   |              |
+//!   |--------------| _estacks
+//! | |              | <<<< psp <<<< | Process stack 0 (pid0) |
+//! | |     SRAM     |               | Process stack 1 (pid1) |
+//! v*|              |               | Process stack 2 (pid2) |
+//!   |              |               | ...
+//!   |--------------| _sstacks
 //!   |              | <<<<<<<<<<<<< | static variables |
 //!   |--------------| 0x2000_0000
 //!   |--------------| 0x1FFF_FFFF
@@ -30,57 +30,258 @@
 //! msp = main stack pointer
 //! ```
 //!
-//! A process has as much memory available, as defined in [PROCESS_MEMORY_SIZE].
+//! Every process declares its own stack size on creation. Stacks are allocated one below
+//! the other, starting at the top of the stack region. Creating a process fails when the
+//! stack region is exhausted.
+//!
+//! The lowest word of every stack holds the [STACK_CANARY]. It is checked on each context
+//! switch, so an overflown stack is detected and the process gets terminated. With the
+//! feature `mpu-stack-guard` the lowest [REGION_SIZE_MIN] bytes of the running process stack
+//! are additionally guarded by the MPU. An overflow then raises the MemManage exception before
+//! any memory of another process gets corrupted.
+//!
+//! The feature `mpu-isolation` restricts unprivileged processes to their own stack, flash and
+//! the regions granted with [grant_region][Scheduler::grant_region], e.g. a data region or
+//! peripherals. The MPU gets reprogrammed for the next process on every context switch. As a
+//! region has to be a power of two aligned to its size, so is every stack with this feature.
+//!
+//! On creation the unused part of each stack is painted with [STACK_PAINT]. Scanning for the
+//! lowest word that has been overwritten results in the maximum stack usage of a process.
 
 pub mod policies;
 
+#[cfg(feature = "mpu-isolation")]
+use crate::cp::mpu::MemoryRegion;
+#[cfg(feature = "mpu-stack-guard")]
+use crate::cp::mpu::{AccessPermission, MemoryAttributes, MemoryProtectionUnit};
+#[cfg(all(feature = "mpu-stack-guard", not(feature = "mpu-isolation")))]
+use crate::cp::mpu::{DEVICE_REGION_SIZE, PERIPHERAL_BASE, SYSTEM_BASE};
 use crate::{
-    cp::stk::SystemTimer,
+    cp::{
+        dwt::DataWatchpointTrace,
+        mpu::{FLASH_BASE, FLASH_SIZE, REGION_SIZE_MIN},
+        scb::{enable_fault_exceptions, is_systick_pending, system_reset},
+        stk::SystemTimer,
+    },
     kernel::{
+        event::{EventCondition, EventGroupId, MAX_EVENT_GROUPS},
         exceptions::trigger_PendSV,
-        scheduler::policies::{Policy, SchedulerPolicy},
+        fault::{halt, Fault, FaultHook, FaultPolicy, RestartPolicy},
+        ipc::MESSAGE_SIZE,
+        mutex::{Mutex, MutexId, MAX_MUTEXES},
+        queue::{QueueId, QueueList},
+        scheduler::policies::SchedulerPolicy,
+        semaphore::{Semaphore, SemaphoreId, MAX_SEMAPHORES},
+        svc::{exit, SvcResult, SysError},
+        time::{Duration, Instant},
+        timer::{TimerId, TimerList, TimerTarget},
     },
-    util::register::Register,
 };
-use core::ptr;
+use core::{cmp::Reverse, mem::size_of, ops::Range, ptr};
 
 use super::cs::CONTEXT_SWITCH;
 
 /// Maximum allowed processes
 const ALLOWED_PROCESSES: usize = 5;
-/// Starting address of processes (processes are stacked descending)
-const PROCESS_BASE: u32 = 0x2000_6000;
-/// The reserved memory for a process. This does not protect against memory overflow.
-const PROCESS_MEMORY_SIZE: u32 = 0x1000;
+/// Minimum stack size of a process in bytes. The [InitialStackFrame] has to fit at least.
+pub const STACK_SIZE_MIN: u32 = 0x100;
+/// Stack size of the idle task (pid0) in bytes.
+const IDLE_STACK_SIZE: u32 = STACK_SIZE_MIN;
+/// Stacks are aligned to the smallest MPU region, so the bottom of each stack can be guarded.
+const STACK_ALIGN: u32 = REGION_SIZE_MIN;
+/// Written to the lowest word of every process stack. An overwritten canary indicates a
+/// stack overflow.
+const STACK_CANARY: u32 = 0xDEAD_BEEF;
+/// Every unused word of a process stack is painted with this pattern, so the high-water mark
+/// of the stack can be measured.
+const STACK_PAINT: u32 = 0xA5A5_A5A5;
+/// MPU region guarding the stack of the running process. Takes priority over all other regions.
+#[cfg(feature = "mpu-stack-guard")]
+const STACK_GUARD_REGION: u32 = 7;
+/// MPU region granting unprivileged processes access to the whole memory. Without it, the
+/// MPU only provides the default memory map to privileged software.
+#[cfg(all(feature = "mpu-stack-guard", not(feature = "mpu-isolation")))]
+const BACKGROUND_REGION: u32 = 0;
+/// MPU region marking the peripherals as device memory within the background region.
+#[cfg(all(feature = "mpu-stack-guard", not(feature = "mpu-isolation")))]
+const PERIPHERAL_REGION: u32 = 1;
+/// MPU region marking the core peripherals as device memory within the background region.
+#[cfg(all(feature = "mpu-stack-guard", not(feature = "mpu-isolation")))]
+const SYSTEM_REGION: u32 = 2;
+/// MPU region granting read and execute access to the flash.
+#[cfg(feature = "mpu-isolation")]
+const FLASH_REGION: u32 = 0;
+/// MPU region granting access to the stack of the running process.
+#[cfg(feature = "mpu-isolation")]
+const STACK_REGION: u32 = 1;
+/// First MPU region for the regions granted to the running process.
+#[cfg(feature = "mpu-isolation")]
+const GRANTED_REGIONS_START: u32 = 2;
+/// Maximum number of regions granted to a process.
+#[cfg(feature = "mpu-isolation")]
+const GRANTED_REGIONS: usize = 5;
+
+extern "C" {
+    /// Lower bound of the stack region. See `link.x`.
+    static _sstacks: u8;
+    /// Upper bound of the stack region. See `link.x`.
+    static _estacks: u8;
+}
 
 /// This [Option] holds a reference to the [Scheduler].
 pub(super) static mut SCHEDULER_REF: Option<&mut Scheduler> = None;
 /// This allows for the singleton pattern.
 static mut SCHEDULER_TAKEN: bool = false;
 
+/// Returns the [Scheduler] once scheduling has started.
+///
+/// # Safety
+///
+/// Only the exceptions and interrupt handlers may use the reference, as they do not preempt
+/// each other.
+pub(super) unsafe fn scheduler_ref() -> Option<&'static mut Scheduler> {
+    (*ptr::addr_of_mut!(SCHEDULER_REF)).as_deref_mut()
+}
+
+#[allow(dead_code)]
+#[repr(C)]
 #[derive(Debug)]
 pub enum SchedulerError {
     /// Process stack is completely occupied.
     ProcessStackFull,
     /// Process was not initialized.
     NotInitialized,
-    /// Process is not available. (Index out of Bounds)
+    /// Process is not available. (Index out of Bounds or pid0)
     NotAvailable,
     /// Process is already running
     AlreadyRunning,
+    /// Period or relative deadline of a periodic process is invalid.
+    InvalidPeriod,
+    /// Requested stack size is below [STACK_SIZE_MIN].
+    InvalidStackSize,
+    /// The stack region has not enough space left for the requested stack size.
+    StackRegionExhausted,
+    /// All regions of the process have been granted already.
+    RegionsExhausted,
+    /// All timers are in use.
+    TimersExhausted,
+    /// There is no such timer or it belongs to another process.
+    NoSuchTimer,
+    /// All semaphores are in use.
+    SemaphoresExhausted,
+    /// There is no such semaphore.
+    NoSuchSemaphore,
+    /// The initial count of a semaphore exceeds its maximum or the maximum is zero. Also
+    /// returned when the lock count of a recursive mutex would overflow and for a message
+    /// queue without capacity or with empty messages.
+    InvalidCount,
+    /// The request is not available right now and the process must not block.
+    WouldBlock,
+    /// All mutexes are in use.
+    MutexesExhausted,
+    /// There is no such mutex.
+    NoSuchMutex,
+    /// The mutex is not owned by the calling process.
+    NotOwner,
+    /// The process would wait forever, e.g. locking a non-recursive mutex it owns already.
+    Deadlock,
+    /// All message queues are in use or their buffer has not enough space left.
+    QueuesExhausted,
+    /// There is no such message queue.
+    NoSuchQueue,
+    /// All event flag groups are in use.
+    EventGroupsExhausted,
+    /// There is no such event flag group.
+    NoSuchEventGroup,
+    /// A process waits for an event flag group without giving any flags.
+    InvalidFlags,
+    /// The process does not wait for a reply of the current process.
+    NotWaitingForReply,
+    /// The current process is not allowed to perform the request, e.g. spawning a process with
+    /// a higher priority than its own.
+    PermissionDenied,
+}
+
+/// Access to memory the kernel performs on behalf of a process, e.g. when a pointer is passed
+/// with a supervisor call.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum MemoryAccess {
+    Read,
+    Write,
+    Execute,
+}
+
+/// Kernel object a [Blocked][ProcessState::Blocked] process waits for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum WaitObject {
+    /// Waits for the expiry of a timer.
+    Timer(TimerId),
+    /// Waits for a unit of a semaphore.
+    Semaphore(SemaphoreId),
+    /// Waits for the ownership of a mutex.
+    Mutex(MutexId),
+    /// Waits for space in a full message queue.
+    QueueSend(QueueId),
+    /// Waits for a message in an empty message queue.
+    QueueReceive(QueueId),
+    /// Waits for flags of an event flag group.
+    EventGroup(EventGroupId),
+    /// Waits until the process with the given pid receives the message sent to it.
+    IpcSend(usize),
+    /// Waits until the process with the given pid replies to the message received from it.
+    IpcReply(usize),
+    /// Waits for a message sent to the process with the given pid, which is the process itself.
+    IpcReceive(usize),
+}
+
+/// The blocking supervisor call of a [Blocked][ProcessState::Blocked] process.
+///
+/// The processes blocked on the same [WaitObject] form its wait queue. The process with the
+/// highest priority is woken up first, processes of equal priority in the order they blocked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct Wait {
+    object: WaitObject,
+    /// Exception frame stacked by the supervisor call. Receives the result on wake-up.
+    frame: u32,
+    /// Order in which the processes blocked.
+    sequence: u32,
+    /// Address of the message to send or of the buffer to receive into, while waiting for a
+    /// message queue or another process.
+    message: u32,
+    /// Address of the buffer receiving the reply, while sending a message to another process.
+    reply: u32,
+    /// Flags waited for, while waiting for an event flag group.
+    condition: Option<EventCondition>,
 }
 
+/// Only processes in state [Ready][ProcessState::Ready] are selected by the policies.
+#[allow(dead_code)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ProcessState {
     Ready,
     Running,
+    /// Waits for an event and is taken out of the run queue until then.
+    Blocked,
+    /// Waits until the kernel time reaches its wake-up tick.
+    Sleeping,
+    /// Has finished or was killed.
+    Terminated,
 }
 
 /// This is process 0 (pid0). It is not intended to be called directly, but is
-/// initiated as a process by the [Scheduler] itself.
-fn scheduler_task() -> ! {
-    let policy = Policy::init(unsafe { SCHEDULER_REF.as_mut().unwrap() });
-    policy.schedule()
+/// initiated as a privileged process by the [Scheduler] itself. It runs whenever no other
+/// process is ready.
+fn idle_task() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Every process returns to this function, as it is set as the link register of the initial
+/// [ExceptionFrame].
+fn process_return() -> ! {
+    exit(0)
 }
 
 /// The scheduler is responsible to create processes and initiate scheduling.
@@ -93,7 +294,25 @@ pub struct Scheduler {
     processes: [Option<ProcessControlBlock>; ALLOWED_PROCESSES],
     policy: SchedulerPolicy,
     current_pid: Option<usize>,
+    last_pid: usize,
     system_timer: SystemTimer,
+    ticks: u64,
+    cycles_per_tick: u32,
+    timers: TimerList,
+    semaphores: [Option<Semaphore>; MAX_SEMAPHORES],
+    mutexes: [Option<Mutex>; MAX_MUTEXES],
+    queues: QueueList,
+    event_groups: [Option<u32>; MAX_EVENT_GROUPS],
+    /// Process to switch to directly on the next scheduling, see [ipc][crate::kernel::ipc].
+    direct_switch: Option<usize>,
+    wait_sequence: u32,
+    stacks_free_top: u32,
+    fault_hook: Option<FaultHook>,
+    fault_policy: FaultPolicy,
+    cycle_counter: Option<DataWatchpointTrace>,
+    switch_cycles: SwitchCycles,
+    #[cfg(feature = "mpu-stack-guard")]
+    mpu: Option<MemoryProtectionUnit>,
 }
 
 impl Scheduler {
@@ -110,83 +329,1596 @@ impl Scheduler {
                 processes: [None; ALLOWED_PROCESSES],
                 policy,
                 current_pid: None,
+                last_pid: ALLOWED_PROCESSES - 1,
                 system_timer,
+                ticks: 0,
+                cycles_per_tick: 0,
+                timers: TimerList::new(),
+                semaphores: [None; MAX_SEMAPHORES],
+                mutexes: [None; MAX_MUTEXES],
+                queues: QueueList::new(),
+                event_groups: [None; MAX_EVENT_GROUPS],
+                direct_switch: None,
+                wait_sequence: 0,
+                stacks_free_top: unsafe { &_estacks as *const u8 as u32 },
+                fault_hook: None,
+                fault_policy: FaultPolicy::Terminate,
+                cycle_counter: None,
+                switch_cycles: SwitchCycles::default(),
+                #[cfg(feature = "mpu-stack-guard")]
+                mpu: None,
             };
 
-            scheduler.create_process(scheduler_task).unwrap();
+            scheduler
+                .create_process(idle_task, IDLE_STACK_SIZE, 0, true)
+                .unwrap();
 
             Some(scheduler)
         }
     }
 
-    /// Disables the system times.
+    /// Advances the kernel time by one tick, wakes up every sleeping process whose
+    /// wake-up tick has been reached and services the expired timers. Gets called by the
+    /// SysTick exception.
+    pub(super) fn tick(&mut self) {
+        self.ticks += 1;
+        let ticks = self.ticks;
+        let mut timed_out = false;
+        for pcb in self.processes.iter_mut().flatten() {
+            timed_out |= pcb.wake_up_if_due(ticks);
+        }
+        if timed_out {
+            self.update_inherited_priorities();
+        }
+        self.service_timers();
+    }
+
+    /// Returns the kernel time in ticks since scheduling started.
+    pub(super) fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Returns the kernel time with the clock cycles elapsed within the current tick. A tick
+    /// that has elapsed, but whose SysTick exception is still pending, is taken into account.
+    pub(super) fn now(&mut self) -> Instant {
+        let mut ticks = self.ticks;
+        let mut current_value = self.system_timer.current_value();
+        if is_systick_pending() {
+            ticks += 1;
+            // The timer might have been reloaded after the first read.
+            current_value = self.system_timer.current_value();
+        }
+        let elapsed = (self.cycles_per_tick - 1).saturating_sub(current_value);
+        Instant::from_cycles(ticks * self.cycles_per_tick as u64 + elapsed as u64)
+    }
+
+    /// Finishes the current job, if the current process is periodic. The process sleeps until
+    /// its next release.
+    pub(super) fn finish_current_job(&mut self) {
+        let ticks = self.ticks;
+        if let Some(pcb) = self.current_pcb() {
+            if let Some(periodic) = pcb.periodic.as_mut() {
+                let release = periodic.next_job();
+                if release > ticks {
+                    pcb.state = ProcessState::Sleeping;
+                    pcb.wake_up = Some(release);
+                }
+            }
+        }
+    }
+
+    /// Puts the current process to sleep until the kernel time reaches `wake_up`.
+    ///
+    /// # Returns
+    ///
+    /// * `false` when `wake_up` has already passed. The process keeps running.
+    pub(super) fn sleep_current_until(&mut self, wake_up: u64) -> bool {
+        if wake_up <= self.ticks {
+            return false;
+        }
+        match self.current_pcb() {
+            Some(pcb) => {
+                pcb.state = ProcessState::Sleeping;
+                pcb.wake_up = Some(wake_up);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Terminates the current process with the given exit code. Its slot will be reused by
+    /// the next process created.
+    pub(super) fn exit_current(&mut self, exit_code: u32) {
+        if let Some(pcb) = self.current_pcb() {
+            pcb.terminate(Some(exit_code));
+            let pid = pcb.pid;
+            self.release_kernel_objects(pid);
+        }
+    }
+
+    /// Terminates the process with the given pid. Its slot will be reused by the next
+    /// process created.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] when the process got terminated.
+    /// * [Err] with [SchedulerError::NotAvailable] for pid0 or an invalid pid.
+    /// * [Err] with [SchedulerError::NotInitialized] if there is no such process.
+    pub(super) fn kill(&mut self, pid: usize) -> Result<(), SchedulerError> {
+        if pid == 0 {
+            return Err(SchedulerError::NotAvailable);
+        }
+        match self.processes.get_mut(pid) {
+            Some(Some(pcb)) if pcb.state != ProcessState::Terminated => {
+                pcb.terminate(None);
+                self.release_kernel_objects(pid);
+                Ok(())
+            }
+            Some(_) => Err(SchedulerError::NotInitialized),
+            None => Err(SchedulerError::NotAvailable),
+        }
+    }
+
+    /// Returns the exit code of a terminated process, as long as its slot has not been reused.
+    #[allow(dead_code)]
+    pub fn exit_code(&self, pid: usize) -> Option<u32> {
+        match self.processes.get(pid) {
+            Some(Some(pcb)) if pcb.state == ProcessState::Terminated => pcb.exit_code,
+            _ => None,
+        }
+    }
+
+    pub(super) fn current_pid(&self) -> Option<usize> {
+        self.current_pid
+    }
+
+    /// Sets the hook that gets called when a process faults.
+    #[allow(dead_code)]
+    pub fn set_fault_hook(&mut self, fault_hook: FaultHook) {
+        self.fault_hook = Some(fault_hook);
+    }
+
+    /// Sets what happens after a fault. Default is [FaultPolicy::Terminate].
+    #[allow(dead_code)]
+    pub fn set_fault_policy(&mut self, fault_policy: FaultPolicy) {
+        self.fault_policy = fault_policy;
+    }
+
+    /// Sets whether the process gets restarted after a fault. Only applies with
+    /// [FaultPolicy::Terminate]. Default is [RestartPolicy::Never].
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] when the policy got set.
+    /// * [Err] with [SchedulerError::NotAvailable] for pid0 or an invalid pid.
+    /// * [Err] with [SchedulerError::NotInitialized] if there is no such process.
+    #[allow(dead_code)]
+    pub fn set_restart_policy(
+        &mut self,
+        pid: usize,
+        restart_policy: RestartPolicy,
+    ) -> Result<(), SchedulerError> {
+        if pid == 0 {
+            return Err(SchedulerError::NotAvailable);
+        }
+        match self.processes.get_mut(pid) {
+            Some(Some(pcb)) if pcb.state != ProcessState::Terminated => {
+                pcb.restart_policy = restart_policy;
+                Ok(())
+            }
+            Some(_) => Err(SchedulerError::NotInitialized),
+            None => Err(SchedulerError::NotAvailable),
+        }
+    }
+
+    /// Returns how often the process has been restarted after a fault.
+    #[allow(dead_code)]
+    pub fn restarts(&self, pid: usize) -> Option<u32> {
+        match self.processes.get(pid) {
+            Some(Some(pcb)) => Some(pcb.restarts),
+            _ => None,
+        }
+    }
+
+    /// Reports the fault to the [FaultHook] and applies the [FaultPolicy]. A fault of the
+    /// idle task (pid0) can not be recovered by terminating the process.
+    ///
+    /// A terminated process releases its slot for the next process created, unless its
+    /// [RestartPolicy] allows for a restart. The restart takes place on the next context
+    /// switch, once the faulted context is discarded.
+    pub(super) fn handle_fault(&mut self, pid: usize, fault: Fault) {
+        if let Some(fault_hook) = self.fault_hook {
+            fault_hook(pid, fault);
+        }
+        match self.fault_policy {
+            FaultPolicy::Halt => halt(),
+            FaultPolicy::Reset => system_reset(),
+            FaultPolicy::Terminate if pid == 0 => halt(),
+            FaultPolicy::Terminate => {}
+        }
+        if let Some(Some(pcb)) = self.processes.get_mut(pid) {
+            pcb.terminate(None);
+            pcb.restart_pending = match pcb.restart_policy {
+                RestartPolicy::Never => false,
+                RestartPolicy::Always => true,
+                RestartPolicy::UpTo(restarts) => pcb.restarts < restarts,
+            };
+            self.release_kernel_objects(pid);
+        }
+    }
+
+    /// Releases the kernel objects of a terminated process, e.g. the timers notifying it. The
+    /// mutexes it owns are handed to their next waiting process.
+    fn release_kernel_objects(&mut self, pid: usize) {
+        self.timers.cancel_targeting(pid);
+        let held_mutexes = match self.processes.get(pid) {
+            Some(Some(pcb)) => pcb.held_mutexes,
+            _ => 0,
+        };
+        for index in 0..MAX_MUTEXES {
+            if held_mutexes & MutexId(index).mask() != 0 {
+                self.release_mutex(pid, MutexId(index));
+            }
+        }
+        // The process might have passed its priority on to the owner of a mutex.
+        self.update_inherited_priorities();
+        // Its clients would wait forever.
+        for object in [WaitObject::IpcSend(pid), WaitObject::IpcReply(pid)] {
+            while self
+                .wake_waiter(object, SvcResult::Error(SysError::NoSuchProcess))
+                .is_some()
+            {}
+        }
+    }
+
+    /// Restarts every process that faulted and is allowed to restart. It keeps its pid, stack,
+    /// priority and privilege, but starts over from its entry function.
+    fn restart_faulted_processes(&mut self) {
+        let ticks = self.ticks;
+        for pcb in self.processes.iter_mut().flatten() {
+            if !pcb.restart_pending {
+                continue;
+            }
+            pcb.psp = pcb.stack.prepare(pcb.entry, pcb.arg);
+            pcb.state = ProcessState::Ready;
+            pcb.exit_code = None;
+            pcb.restart_pending = false;
+            pcb.restarts += 1;
+            if let Some(periodic) = pcb.periodic.as_mut() {
+                *periodic = PeriodicTask::init(periodic.period, periodic.deadline, ticks);
+            }
+        }
+    }
+
+    /// Applies the [FaultPolicy] to a fault of the kernel itself. The system is halted, unless
+    /// it is reset.
+    pub(super) fn handle_kernel_fault(&self) -> ! {
+        match self.fault_policy {
+            FaultPolicy::Reset => system_reset(),
+            _ => halt(),
+        }
+    }
+
+    /// Returns the address range at the bottom of the stack of the given process that is
+    /// guarded by the MPU.
+    pub(super) fn stack_guard(&self, pid: usize) -> Option<Range<u32>> {
+        if !cfg!(feature = "mpu-stack-guard") {
+            return None;
+        }
+        match self.processes.get(pid) {
+            Some(Some(pcb)) => Some(pcb.stack.bottom..pcb.stack.bottom + REGION_SIZE_MIN),
+            _ => None,
+        }
+    }
+
+    /// Checks the [STACK_CANARY] of every process stack. A process whose canary has been
+    /// overwritten is handled as [Fault::StackOverflow].
+    pub(super) fn check_stack_canaries(&mut self) {
+        for pid in 0..ALLOWED_PROCESSES {
+            let overflown = match &self.processes[pid] {
+                Some(pcb) => pcb.state != ProcessState::Terminated && !pcb.stack.is_canary_intact(),
+                None => false,
+            };
+            if overflown {
+                self.handle_fault(pid, Fault::StackOverflow);
+            }
+        }
+    }
+
+    /// Returns the maximum number of bytes the process has used of its stack so far.
+    ///
+    /// The lowest [REGION_SIZE_MIN] bytes of a stack are not measured, as they may be
+    /// guarded by the MPU. A stack overflow is detected by the [STACK_CANARY] instead.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with the high-water mark in bytes.
+    /// * [Err] with [SchedulerError::NotAvailable] for an invalid pid.
+    /// * [Err] with [SchedulerError::NotInitialized] if there is no such process.
+    pub fn stack_usage(&self, pid: usize) -> Result<u32, SchedulerError> {
+        match self.processes.get(pid) {
+            Some(Some(pcb)) if pcb.state != ProcessState::Terminated => {
+                Ok(pcb.stack.high_water_mark())
+            }
+            Some(_) => Err(SchedulerError::NotInitialized),
+            None => Err(SchedulerError::NotAvailable),
+        }
+    }
+
+    /// Returns true when the process runs privileged.
+    pub(super) fn is_privileged(&self, pid: usize) -> bool {
+        matches!(self.processes.get(pid), Some(Some(pcb)) if pcb.privileged)
+    }
+
+    /// Returns the memory range containing `addr` the process may access as requested. That is
+    /// its own stack, the flash for reading and executing and with the feature `mpu-isolation`
+    /// its granted regions. A privileged process may access any memory.
+    ///
+    /// # Returns
+    ///
+    /// * [Some] with the range from `addr` to the end of the accessible memory.
+    /// * [None] when the process has no such access to `addr` or there is no such process.
+    pub(super) fn accessible_range(
+        &self,
+        pid: usize,
+        addr: u32,
+        access: MemoryAccess,
+    ) -> Option<Range<u32>> {
+        let pcb = match self.processes.get(pid) {
+            Some(Some(pcb)) => pcb,
+            _ => return None,
+        };
+        if pcb.privileged {
+            return Some(addr..u32::MAX);
+        }
+
+        // The guarded bottom of the stack is not accessible to the process either. It holds the
+        // canary, which must not be overwritten through the kernel.
+        let stack_bottom = match self.stack_guard(pid) {
+            Some(guard) => guard.end,
+            None => pcb.stack.bottom + size_of::<u32>() as u32,
+        };
+        let stack = stack_bottom..pcb.stack.top();
+        if access != MemoryAccess::Execute && stack.contains(&addr) {
+            return Some(addr..stack.end);
+        }
+
+        let flash = FLASH_BASE..FLASH_BASE + FLASH_SIZE;
+        if access != MemoryAccess::Write && flash.contains(&addr) {
+            return Some(addr..flash.end);
+        }
+
+        #[cfg(feature = "mpu-isolation")]
+        for region in pcb.granted_regions.iter().flatten() {
+            let permitted = match access {
+                MemoryAccess::Read => region.permission().is_user_readable(),
+                MemoryAccess::Write => region.permission().is_user_writable(),
+                MemoryAccess::Execute => {
+                    region.permission().is_user_readable() && !region.is_execute_never()
+                }
+            };
+            let end = region.base().saturating_add(region.size());
+            if permitted && (region.base()..end).contains(&addr) {
+                return Some(addr..end);
+            }
+        }
+
+        None
+    }
+
+    /// Returns true when the process may access `len` bytes starting at `addr` as requested.
+    /// See [accessible_range][Scheduler::accessible_range].
+    pub(super) fn is_accessible(
+        &self,
+        pid: usize,
+        addr: u32,
+        len: u32,
+        access: MemoryAccess,
+    ) -> bool {
+        match (
+            self.accessible_range(pid, addr, access),
+            addr.checked_add(len),
+        ) {
+            (Some(range), Some(end)) => end <= range.end,
+            _ => false,
+        }
+    }
+
+    /// Guards the stack of the running process with a no-access region of the MPU. An access
+    /// raises the MemManage exception, which reports a stack overflow. Unprivileged processes keep
+    /// access to the remaining memory through a background region, unless the feature
+    /// `mpu-isolation` is enabled. The background region is normal memory, except for the
+    /// peripheral and the system region, which are device memory.
+    #[cfg(feature = "mpu-stack-guard")]
+    pub fn set_stack_guard(&mut self, mut mpu: MemoryProtectionUnit) {
+        // Flash and SRAM are normal memory, the peripherals on top of it device memory.
+        #[cfg(not(feature = "mpu-isolation"))]
+        mpu.set_background_region(
+            BACKGROUND_REGION,
+            AccessPermission::FullAccess,
+            MemoryAttributes::Sram,
+        )
+        .set_region(
+            PERIPHERAL_REGION,
+            PERIPHERAL_BASE,
+            DEVICE_REGION_SIZE,
+            AccessPermission::FullAccess,
+            MemoryAttributes::Device,
+            true,
+        )
+        .set_region(
+            SYSTEM_REGION,
+            SYSTEM_BASE,
+            DEVICE_REGION_SIZE,
+            AccessPermission::FullAccess,
+            MemoryAttributes::Device,
+            true,
+        );
+        mpu.enable();
+        self.mpu = Some(mpu);
+    }
+
+    /// Grants the process access to a memory region, e.g. its data or a peripheral.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] when the region got granted.
+    /// * [Err] with [SchedulerError::RegionsExhausted] if the process has no region left.
+    /// * [Err] with [SchedulerError::NotAvailable] for an invalid pid.
+    /// * [Err] with [SchedulerError::NotInitialized] if there is no such process.
+    #[cfg(feature = "mpu-isolation")]
+    #[allow(dead_code)]
+    pub fn grant_region(&mut self, pid: usize, region: MemoryRegion) -> Result<(), SchedulerError> {
+        match self.processes.get_mut(pid) {
+            Some(Some(pcb)) if pcb.state != ProcessState::Terminated => {
+                match pcb.granted_regions.iter_mut().find(|slot| slot.is_none()) {
+                    Some(slot) => {
+                        *slot = Some(region);
+                        Ok(())
+                    }
+                    None => Err(SchedulerError::RegionsExhausted),
+                }
+            }
+            Some(_) => Err(SchedulerError::NotInitialized),
+            None => Err(SchedulerError::NotAvailable),
+        }
+    }
+
+    /// Reprograms the MPU, so the given process can only access flash, its own stack and
+    /// its granted regions.
+    #[cfg(feature = "mpu-isolation")]
+    pub(super) fn isolate(&mut self, pid: usize) {
+        if let (Some(mpu), Some(Some(pcb))) = (self.mpu.as_mut(), self.processes.get(pid)) {
+            mpu.set_region(
+                FLASH_REGION,
+                FLASH_BASE,
+                FLASH_SIZE,
+                AccessPermission::ReadOnly,
+                MemoryAttributes::Flash,
+                false,
+            )
+            .set_region(
+                STACK_REGION,
+                pcb.stack.bottom,
+                pcb.stack.size,
+                AccessPermission::FullAccess,
+                MemoryAttributes::Sram,
+                true,
+            );
+            for (region, granted) in (GRANTED_REGIONS_START..).zip(pcb.granted_regions.iter()) {
+                match granted {
+                    Some(memory_region) => mpu.set_memory_region(region, memory_region),
+                    None => mpu.disable_region(region),
+                };
+            }
+        }
+    }
+
+    /// Removes the stack guard. Needs to be done before the kernel accesses the bottom of
+    /// a stack.
+    #[cfg(feature = "mpu-stack-guard")]
+    pub(super) fn unguard_stack(&mut self) {
+        if let Some(mpu) = self.mpu.as_mut() {
+            mpu.disable_region(STACK_GUARD_REGION);
+        }
+    }
+
+    /// Guards the lowest [REGION_SIZE_MIN] bytes of the stack of the given process.
+    #[cfg(feature = "mpu-stack-guard")]
+    pub(super) fn guard_stack(&mut self, pid: usize) {
+        if let (Some(mpu), Some(Some(pcb))) = (self.mpu.as_mut(), self.processes.get(pid)) {
+            mpu.set_region(
+                STACK_GUARD_REGION,
+                pcb.stack.bottom,
+                REGION_SIZE_MIN,
+                AccessPermission::NoAccess,
+                MemoryAttributes::Sram,
+                true,
+            );
+        }
+    }
+
+    /// Creates a timer that expires after `duration`, and every `duration` afterwards if
+    /// `periodic`. The duration is rounded up to whole ticks of the system timer.
+    ///
+    /// A [TimerTarget::Callback] runs privileged within the SysTick exception. Therefore, only
+    /// the kernel can create such timers, processes create timers notifying themselves.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with the id of the timer.
+    /// * [Err] with [SchedulerError::TimersExhausted] if all timers are in use.
+    #[allow(dead_code)]
+    pub fn create_timer(
+        &mut self,
+        duration: Duration,
+        periodic: bool,
+        target: TimerTarget,
+    ) -> Result<TimerId, SchedulerError> {
+        let ticks = self.duration_to_ticks(duration);
+        let period = if periodic { Some(ticks) } else { None };
+        self.timers
+            .create(self.ticks + ticks, period, target)
+            .ok_or(SchedulerError::TimersExhausted)
+    }
+
+    /// Removes the timer. A process waiting for it returns with
+    /// [InvalidArgument][SysError::InvalidArgument], as the timer is gone.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SchedulerError::NoSuchTimer] if there is no such timer.
+    #[allow(dead_code)]
+    pub fn cancel_timer(&mut self, id: TimerId) -> Result<(), SchedulerError> {
+        if self.timers.target(id).is_none() {
+            return Err(SchedulerError::NoSuchTimer);
+        }
+        let error = SvcResult::Error(SysError::InvalidArgument);
+        while self.wake_waiter(WaitObject::Timer(id), error).is_some() {}
+        self.timers.cancel(id);
+        Ok(())
+    }
+
+    /// Creates a timer notifying the current process. Gets called by the supervisor call
+    /// [TimerCreate][super::svc::SvcRequest::TimerCreate].
+    pub(super) fn create_timer_for_current(
+        &mut self,
+        duration: Duration,
+        periodic: bool,
+    ) -> Result<TimerId, SchedulerError> {
+        let pid = self.current_pid.ok_or(SchedulerError::NotInitialized)?;
+        self.create_timer(duration, periodic, TimerTarget::Process(pid))
+    }
+
+    /// Removes a timer notifying the current process.
+    pub(super) fn cancel_timer_for_current(&mut self, id: TimerId) -> Result<(), SchedulerError> {
+        self.check_timer_target(id)?;
+        self.cancel_timer(id)
+    }
+
+    /// Blocks the current process until the timer expires. Returns immediately if it has
+    /// expired already since the process waited last. `frame` is the exception frame of the
+    /// supervisor call, see [Wait].
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with `true` when the process got blocked.
+    /// * [Err] with [SchedulerError::NoSuchTimer] if there is no such timer notifying the
+    ///   current process.
+    pub(super) fn wait_timer_current(
+        &mut self,
+        id: TimerId,
+        frame: u32,
+    ) -> Result<bool, SchedulerError> {
+        self.check_timer_target(id)?;
+        if self.timers.take_pending(id) {
+            self.timers.release_if_fired(id);
+            return Ok(false);
+        }
+        self.block_current(WaitObject::Timer(id), frame, None);
+        Ok(true)
+    }
+
+    /// Checks that the timer notifies the current process, which must not access the timers
+    /// of other processes.
+    fn check_timer_target(&self, id: TimerId) -> Result<(), SchedulerError> {
+        match (self.timers.target(id), self.current_pid) {
+            (Some(TimerTarget::Process(target)), Some(pid)) if target == pid => Ok(()),
+            _ => Err(SchedulerError::NoSuchTimer),
+        }
+    }
+
+    /// Handles every expired timer. A notified process that waits for the timer gets ready,
+    /// otherwise the expiry is counted for its next wait. A one-shot timer is removed once its
+    /// expiry has been delivered.
+    fn service_timers(&mut self) {
+        while let Some((id, target)) = self.timers.pop_expired(self.ticks) {
+            match target {
+                TimerTarget::Callback(callback) => callback(id),
+                TimerTarget::Process(_) => {
+                    if self
+                        .wake_waiter(WaitObject::Timer(id), SvcResult::None)
+                        .is_none()
+                    {
+                        self.timers.add_pending(id);
+                    }
+                }
+            }
+            self.timers.release_if_fired(id);
+        }
+    }
+
+    /// Creates a semaphore with `initial` of `max` units available. A binary semaphore has a
+    /// maximum of one.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with the id of the semaphore.
+    /// * [Err] with [SchedulerError::InvalidCount] if `initial` exceeds `max` or `max` is zero.
+    /// * [Err] with [SchedulerError::SemaphoresExhausted] if all semaphores are in use.
+    pub fn create_semaphore(
+        &mut self,
+        initial: u32,
+        max: u32,
+    ) -> Result<SemaphoreId, SchedulerError> {
+        let semaphore = Semaphore::new(initial, max).ok_or(SchedulerError::InvalidCount)?;
+        let index = self
+            .semaphores
+            .iter()
+            .position(|slot| slot.is_none())
+            .ok_or(SchedulerError::SemaphoresExhausted)?;
+        self.semaphores[index] = Some(semaphore);
+        Ok(SemaphoreId(index))
+    }
+
+    /// Takes a unit of the semaphore for the current process. If none is available, the process
+    /// gets blocked until a unit is posted or the `timeout` in ticks expires. `frame` is the
+    /// exception frame of the supervisor call, see [Wait].
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with `true` when the process got blocked.
+    /// * [Err] with [SchedulerError::WouldBlock] if `timeout` is zero and no unit is available.
+    /// * [Err] with [SchedulerError::NoSuchSemaphore] if there is no such semaphore.
+    pub(super) fn wait_semaphore_current(
+        &mut self,
+        id: SemaphoreId,
+        timeout: Option<Duration>,
+        frame: u32,
+    ) -> Result<bool, SchedulerError> {
+        let semaphore = match self.semaphores.get_mut(id.0) {
+            Some(Some(semaphore)) => semaphore,
+            _ => return Err(SchedulerError::NoSuchSemaphore),
+        };
+        if semaphore.try_take() {
+            return Ok(false);
+        }
+        let timeout = self.timeout_to_ticks(timeout)?;
+        self.block_current(WaitObject::Semaphore(id), frame, timeout);
+        Ok(true)
+    }
+
+    /// Posts a unit of the semaphore. It is handed directly to the first process in its wait
+    /// queue, if there is one.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with `true` when a waiting process got woken up.
+    /// * [Err] with [SchedulerError::NoSuchSemaphore] if there is no such semaphore.
+    pub(super) fn post_semaphore(&mut self, id: SemaphoreId) -> Result<bool, SchedulerError> {
+        if !matches!(self.semaphores.get(id.0), Some(Some(_))) {
+            return Err(SchedulerError::NoSuchSemaphore);
+        }
+        if self
+            .wake_waiter(WaitObject::Semaphore(id), SvcResult::None)
+            .is_some()
+        {
+            return Ok(true);
+        }
+        if let Some(Some(semaphore)) = self.semaphores.get_mut(id.0) {
+            semaphore.give();
+        }
+        Ok(false)
+    }
+
+    /// Creates a mutex. The owner of a `recursive` mutex may lock it repeatedly.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with the id of the mutex.
+    /// * [Err] with [SchedulerError::MutexesExhausted] if all mutexes are in use.
+    pub fn create_mutex(&mut self, recursive: bool) -> Result<MutexId, SchedulerError> {
+        let index = self
+            .mutexes
+            .iter()
+            .position(|slot| slot.is_none())
+            .ok_or(SchedulerError::MutexesExhausted)?;
+        self.mutexes[index] = Some(Mutex::new(recursive));
+        Ok(MutexId(index))
+    }
+
+    /// Locks the mutex for the current process. If another process owns it, the current
+    /// process gets blocked until the mutex is handed to it or the `timeout` expires. The owner
+    /// inherits the priority of the blocked process, if it is higher. `frame` is the exception
+    /// frame of the supervisor call, see [Wait].
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with `true` when the process got blocked.
+    /// * [Err] with [SchedulerError::WouldBlock] if `timeout` is zero and the mutex is owned
+    ///   by another process.
+    /// * [Err] with [SchedulerError::Deadlock] if the process owns the non-recursive mutex
+    ///   already.
+    /// * [Err] with [SchedulerError::NoSuchMutex] if there is no such mutex.
+    pub(super) fn lock_mutex_current(
+        &mut self,
+        id: MutexId,
+        timeout: Option<Duration>,
+        frame: u32,
+    ) -> Result<bool, SchedulerError> {
+        let pid = self.current_pid.ok_or(SchedulerError::NotInitialized)?;
+        let mutex = match self.mutexes.get_mut(id.0) {
+            Some(Some(mutex)) => mutex,
+            _ => return Err(SchedulerError::NoSuchMutex),
+        };
+        match mutex.owner {
+            None => {
+                mutex.hand_to(pid);
+                if let Some(pcb) = self.current_pcb() {
+                    pcb.held_mutexes |= id.mask();
+                }
+                Ok(false)
+            }
+            Some(owner) if owner == pid => {
+                if !mutex.recursive {
+                    return Err(SchedulerError::Deadlock);
+                }
+                mutex.lock_count = mutex
+                    .lock_count
+                    .checked_add(1)
+                    .ok_or(SchedulerError::InvalidCount)?;
+                Ok(false)
+            }
+            Some(_) => {
+                let timeout = self.timeout_to_ticks(timeout)?;
+                self.block_current(WaitObject::Mutex(id), frame, timeout);
+                self.update_inherited_priorities();
+                Ok(true)
+            }
+        }
+    }
+
+    /// Unlocks the mutex owned by the current process. Once it is unlocked as often as it was
+    /// locked, it is handed to the first process in its wait queue, if there is one.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with `true` when a waiting process got woken up.
+    /// * [Err] with [SchedulerError::NotOwner] if the current process does not own the mutex.
+    /// * [Err] with [SchedulerError::NoSuchMutex] if there is no such mutex.
+    pub(super) fn unlock_mutex_current(&mut self, id: MutexId) -> Result<bool, SchedulerError> {
+        let pid = self.current_pid.ok_or(SchedulerError::NotInitialized)?;
+        let mutex = match self.mutexes.get_mut(id.0) {
+            Some(Some(mutex)) => mutex,
+            _ => return Err(SchedulerError::NoSuchMutex),
+        };
+        if mutex.owner != Some(pid) {
+            return Err(SchedulerError::NotOwner);
+        }
+        mutex.lock_count -= 1;
+        if mutex.lock_count > 0 {
+            return Ok(false);
+        }
+        Ok(self.release_mutex(pid, id))
+    }
+
+    /// Takes the mutex from its owner `pid` and hands it to the first process in its wait
+    /// queue. Returns `true` when a waiting process got woken up.
+    fn release_mutex(&mut self, pid: usize, id: MutexId) -> bool {
+        if let Some(Some(pcb)) = self.processes.get_mut(pid) {
+            pcb.held_mutexes &= !id.mask();
+        }
+        let next = self.wake_waiter(WaitObject::Mutex(id), SvcResult::None);
+        if let Some(Some(mutex)) = self.mutexes.get_mut(id.0) {
+            match next {
+                Some(next) => mutex.hand_to(next),
+                None => *mutex = Mutex::new(mutex.recursive),
+            }
+        }
+        if let Some(Some(pcb)) = next.and_then(|next| self.processes.get_mut(next)) {
+            pcb.held_mutexes |= id.mask();
+        }
+        self.update_inherited_priorities();
+        next.is_some()
+    }
+
+    /// Resets every process to its own priority and lets the owner of each mutex inherit the
+    /// highest priority of the processes waiting for it.
+    fn update_inherited_priorities(&mut self) {
+        for pcb in self.processes.iter_mut().flatten() {
+            pcb.priority = pcb.base_priority;
+        }
+        // Each pass passes the priorities one owner further along a chain of owners waiting for
+        // mutexes themselves. A chain is at most as long as there are processes.
+        for _ in 0..ALLOWED_PROCESSES {
+            let mut changed = false;
+            for waiter in 0..ALLOWED_PROCESSES {
+                let (id, priority) = match self.processes[waiter] {
+                    Some(pcb) => match pcb.wait {
+                        Some(Wait {
+                            object: WaitObject::Mutex(id),
+                            ..
+                        }) if pcb.state == ProcessState::Blocked => (id, pcb.priority),
+                        _ => continue,
+                    },
+                    None => continue,
+                };
+                let owner = match self.mutexes.get(id.0) {
+                    Some(Some(mutex)) => mutex.owner,
+                    _ => None,
+                };
+                if let Some(Some(pcb)) = owner.and_then(|owner| self.processes.get_mut(owner)) {
+                    if pcb.priority < priority {
+                        pcb.priority = priority;
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Creates an empty message queue for `capacity` messages of `message_size` bytes. See
+    /// [MessageQueue][crate::kernel::queue::MessageQueue] for a typed queue.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with the id of the queue.
+    /// * [Err] with [SchedulerError::InvalidCount] if `capacity` or `message_size` is zero.
+    /// * [Err] with [SchedulerError::QueuesExhausted] if all queues are in use or there is not
+    ///   enough space left for the messages.
+    pub fn create_queue(
+        &mut self,
+        message_size: usize,
+        capacity: usize,
+    ) -> Result<QueueId, SchedulerError> {
+        self.queues.create(message_size, capacity)
+    }
+
+    /// Returns the size in bytes of the messages of the queue.
+    pub(super) fn queue_message_size(&mut self, id: QueueId) -> Result<u32, SchedulerError> {
+        Ok(self.queues.get_mut(id)?.message_size() as u32)
+    }
+
+    /// Sends the message at `message` from the current process. It is copied directly to the
+    /// first process waiting to receive, if there is one. If the queue is full, the current
+    /// process gets blocked until a message is received or the `timeout` expires. `frame` is
+    /// the exception frame of the supervisor call, see [Wait].
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with `true` when scheduling is due, as the process got blocked or woke up a
+    ///   receiver.
+    /// * [Err] with [SchedulerError::WouldBlock] if `timeout` is zero and the queue is full.
+    /// * [Err] with [SchedulerError::NoSuchQueue] if there is no such queue.
+    pub(super) fn send_queue_current(
+        &mut self,
+        id: QueueId,
+        message: u32,
+        timeout: Option<Duration>,
+        frame: u32,
+    ) -> Result<bool, SchedulerError> {
+        let message_size = self.queue_message_size(id)? as usize;
+        // Receivers only wait while the queue is empty.
+        if let Some(receiver) = self.first_waiter(WaitObject::QueueReceive(id)) {
+            if let Some(wait) = receiver.wait {
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        message as *const u8,
+                        wait.message as *mut u8,
+                        message_size,
+                    )
+                };
+            }
+            receiver.complete_wait(SvcResult::None);
+            return Ok(true);
+        }
+
+        let queue = self.queues.get_mut(id)?;
+        if !queue.is_full() {
+            unsafe { queue.push(message as *const u8) };
+            return Ok(false);
+        }
+        let timeout = self.timeout_to_ticks(timeout)?;
+        self.block_current_with_message(WaitObject::QueueSend(id), frame, timeout, message);
+        Ok(true)
+    }
+
+    /// Receives the oldest message of the queue into `buffer` of the current process. The slot
+    /// freed takes the message of the first process waiting to send, if there is one. If the
+    /// queue is empty, the current process gets blocked until a message is sent or the
+    /// `timeout` expires. `frame` is the exception frame of the supervisor call, see [Wait].
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with `true` when scheduling is due, as the process got blocked or woke up a
+    ///   sender.
+    /// * [Err] with [SchedulerError::WouldBlock] if `timeout` is zero and the queue is empty.
+    /// * [Err] with [SchedulerError::NoSuchQueue] if there is no such queue.
+    pub(super) fn receive_queue_current(
+        &mut self,
+        id: QueueId,
+        buffer: u32,
+        timeout: Option<Duration>,
+        frame: u32,
+    ) -> Result<bool, SchedulerError> {
+        let queue = self.queues.get_mut(id)?;
+        if queue.is_empty() {
+            let timeout = self.timeout_to_ticks(timeout)?;
+            self.block_current_with_message(WaitObject::QueueReceive(id), frame, timeout, buffer);
+            return Ok(true);
+        }
+        unsafe { queue.pop(buffer as *mut u8) };
+
+        let message = match self.first_waiter(WaitObject::QueueSend(id)) {
+            Some(sender) => {
+                let message = sender.wait.map(|wait| wait.message);
+                sender.complete_wait(SvcResult::None);
+                message
+            }
+            None => return Ok(false),
+        };
+        if let Some(message) = message {
+            unsafe { self.queues.get_mut(id)?.push(message as *const u8) };
+        }
+        Ok(true)
+    }
+
+    /// Creates an event flag group with all flags cleared.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with the id of the group.
+    /// * [Err] with [SchedulerError::EventGroupsExhausted] if all groups are in use.
+    pub fn create_event_group(&mut self) -> Result<EventGroupId, SchedulerError> {
+        let index = self
+            .event_groups
+            .iter()
+            .position(|slot| slot.is_none())
+            .ok_or(SchedulerError::EventGroupsExhausted)?;
+        self.event_groups[index] = Some(0);
+        Ok(EventGroupId(index))
+    }
+
+    /// Sets the flags of the group and wakes up every process whose condition is met. They
+    /// receive the flags as set now. The flags to clear on wake-up are cleared afterwards.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with `true` when a waiting process got woken up.
+    /// * [Err] with [SchedulerError::NoSuchEventGroup] if there is no such group.
+    pub(super) fn set_event_flags(
+        &mut self,
+        id: EventGroupId,
+        flags: u32,
+    ) -> Result<bool, SchedulerError> {
+        let group = match self.event_groups.get_mut(id.0) {
+            Some(Some(group)) => group,
+            _ => return Err(SchedulerError::NoSuchEventGroup),
+        };
+        *group |= flags;
+        let flags = *group;
+
+        let mut woken = false;
+        let mut clear = 0;
+        for pcb in self.processes.iter_mut().flatten() {
+            if !pcb.is_blocked_on(WaitObject::EventGroup(id)) {
+                continue;
+            }
+            if let Some(condition) = pcb.wait.and_then(|wait| wait.condition) {
+                if condition.is_met(flags) {
+                    if condition.clear {
+                        clear |= condition.mask;
+                    }
+                    pcb.complete_wait(SvcResult::Flags(flags));
+                    woken = true;
+                }
+            }
+        }
+        if let Some(Some(group)) = self.event_groups.get_mut(id.0) {
+            *group &= !clear;
+        }
+        Ok(woken)
+    }
+
+    /// Clears the flags of the group.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SchedulerError::NoSuchEventGroup] if there is no such group.
+    pub(super) fn clear_event_flags(
+        &mut self,
+        id: EventGroupId,
+        flags: u32,
+    ) -> Result<(), SchedulerError> {
+        match self.event_groups.get_mut(id.0) {
+            Some(Some(group)) => {
+                *group &= !flags;
+                Ok(())
+            }
+            _ => Err(SchedulerError::NoSuchEventGroup),
+        }
+    }
+
+    /// Waits for the flags of the group given by the condition. If the condition is not met,
+    /// the current process gets blocked until flags are set meeting it or the `timeout`
+    /// expires. `frame` is the exception frame of the supervisor call, see [Wait].
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with the flags, if the condition is met already.
+    /// * [Ok] with [None] when the process got blocked.
+    /// * [Err] with [SchedulerError::WouldBlock] if `timeout` is zero and the condition is not
+    ///   met.
+    /// * [Err] with [SchedulerError::InvalidFlags] if the mask is empty.
+    /// * [Err] with [SchedulerError::NoSuchEventGroup] if there is no such group.
+    pub(super) fn wait_event_flags_current(
+        &mut self,
+        id: EventGroupId,
+        condition: EventCondition,
+        timeout: Option<Duration>,
+        frame: u32,
+    ) -> Result<Option<u32>, SchedulerError> {
+        if condition.mask == 0 {
+            return Err(SchedulerError::InvalidFlags);
+        }
+        let group = match self.event_groups.get_mut(id.0) {
+            Some(Some(group)) => group,
+            _ => return Err(SchedulerError::NoSuchEventGroup),
+        };
+        let flags = *group;
+        if condition.is_met(flags) {
+            if condition.clear {
+                *group &= !condition.mask;
+            }
+            return Ok(Some(flags));
+        }
+
+        let timeout = self.timeout_to_ticks(timeout)?;
+        self.block_current(WaitObject::EventGroup(id), frame, timeout);
+        if let Some(wait) = self.current_pcb().and_then(|pcb| pcb.wait.as_mut()) {
+            wait.condition = Some(condition);
+        }
+        Ok(None)
+    }
+
+    /// Sends the message at `message` from the current process to the process `pid` and blocks
+    /// the current process until `pid` replies into `reply`. If `pid` waits in receive, the
+    /// message is copied to it right away and `pid` is preferred on the next scheduling.
+    /// `frame` is the exception frame of the supervisor call, see [Wait].
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SchedulerError::Deadlock] if the current process sends to itself.
+    /// * [Err] with [SchedulerError::NotInitialized] if there is no such process.
+    /// * [Err] with [SchedulerError::NotAvailable] for pid0 or an invalid pid.
+    pub(super) fn send_current(
+        &mut self,
+        pid: usize,
+        message: u32,
+        reply: u32,
+        frame: u32,
+    ) -> Result<(), SchedulerError> {
+        let sender = self.current_pid.ok_or(SchedulerError::NotInitialized)?;
+        if pid == sender {
+            return Err(SchedulerError::Deadlock);
+        }
+        match self.processes.get(pid) {
+            Some(Some(pcb)) if pid != 0 && pcb.state != ProcessState::Terminated => {}
+            Some(_) if pid != 0 => return Err(SchedulerError::NotInitialized),
+            _ => return Err(SchedulerError::NotAvailable),
+        }
+
+        let received = match self.processes[pid].as_mut() {
+            Some(pcb) if pcb.is_blocked_on(WaitObject::IpcReceive(pid)) => {
+                if let Some(wait) = pcb.wait {
+                    unsafe { copy_message(message, wait.message) };
+                }
+                pcb.complete_wait(SvcResult::Pid(sender));
+                true
+            }
+            _ => false,
+        };
+        let object = if received {
+            WaitObject::IpcReply(pid)
+        } else {
+            WaitObject::IpcSend(pid)
+        };
+        self.block_current(object, frame, None);
+        if let Some(wait) = self.current_pcb().and_then(|pcb| pcb.wait.as_mut()) {
+            wait.message = message;
+            wait.reply = reply;
+        }
+        if received {
+            self.direct_switch = Some(pid);
+        }
+        Ok(())
+    }
+
+    /// Receives the message of the first process sending to the current process into
+    /// `buffer`. The sender then waits for the reply. If no process is sending, the current
+    /// process gets blocked until one does. `frame` is the exception frame of the supervisor
+    /// call, see [Wait].
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] with the pid of the sender, if a process was sending already.
+    /// * [Ok] with [None] when the current process got blocked.
+    pub(super) fn receive_current(
+        &mut self,
+        buffer: u32,
+        frame: u32,
+    ) -> Result<Option<usize>, SchedulerError> {
+        let pid = self.current_pid.ok_or(SchedulerError::NotInitialized)?;
+        if let Some(sender) = self.first_waiter(WaitObject::IpcSend(pid)) {
+            if let Some(wait) = sender.wait.as_mut() {
+                unsafe { copy_message(wait.message, buffer) };
+                wait.object = WaitObject::IpcReply(pid);
+            }
+            return Ok(Some(sender.pid));
+        }
+        self.block_current_with_message(WaitObject::IpcReceive(pid), frame, None, buffer);
+        Ok(None)
+    }
+
+    /// Replies to the process `pid` with the message at `message`. It was received from `pid`
+    /// by the current process. `pid` gets woken up and is preferred on the next scheduling.
     ///
-    /// Furthermore, it also clears a possible pending flag for the SysTick exception triggered
-    /// by the system timer. For this to be effective, this function needs to be called within
-    /// an exception, as for example in the supervisor call.
-    pub(super) fn disable_timed_context_switch(&mut self) {
-        self.system_timer.disable();
-        let icsr: &mut Register = unsafe { &mut *(0xE000_ED04 as *mut Register) };
-        icsr.set_bit(25);
+    /// # Returns
+    ///
+    /// * [Err] with [SchedulerError::NotWaitingForReply] if `pid` does not wait for a reply of
+    ///   the current process.
+    pub(super) fn reply_current(&mut self, pid: usize, message: u32) -> Result<(), SchedulerError> {
+        let replier = self.current_pid.ok_or(SchedulerError::NotInitialized)?;
+        match self.processes.get_mut(pid) {
+            Some(Some(pcb)) if pcb.is_blocked_on(WaitObject::IpcReply(replier)) => {
+                if let Some(wait) = pcb.wait {
+                    unsafe { copy_message(message, wait.reply) };
+                }
+                pcb.complete_wait(SvcResult::None);
+                self.direct_switch = Some(pid);
+                Ok(())
+            }
+            _ => Err(SchedulerError::NotWaitingForReply),
+        }
     }
 
-    /// This function will start the scheduling of the created processes. First task will
-    /// be [scheduler_task] also known as pid0.
+    /// Converts the timeout of a blocking supervisor call into ticks. [None] waits forever.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SchedulerError::WouldBlock] for a zero timeout, which must not block.
+    fn timeout_to_ticks(&self, timeout: Option<Duration>) -> Result<Option<u64>, SchedulerError> {
+        match timeout {
+            Some(duration) if duration.as_cycles() == 0 => Err(SchedulerError::WouldBlock),
+            Some(duration) => Ok(Some(self.duration_to_ticks(duration))),
+            None => Ok(None),
+        }
+    }
+
+    /// Blocks the current process on the object, optionally until `timeout` ticks have passed.
+    /// `frame` is the exception frame of the blocking supervisor call, see [Wait].
+    fn block_current(&mut self, object: WaitObject, frame: u32, timeout: Option<u64>) {
+        let sequence = self.wait_sequence;
+        self.wait_sequence = self.wait_sequence.wrapping_add(1);
+        let wake_up = timeout.map(|ticks| self.ticks + ticks);
+        if let Some(pcb) = self.current_pcb() {
+            pcb.state = ProcessState::Blocked;
+            pcb.wake_up = wake_up;
+            pcb.wait = Some(Wait {
+                object,
+                frame,
+                sequence,
+                message: 0,
+                reply: 0,
+                condition: None,
+            });
+        }
+    }
+
+    /// Blocks the current process on a message queue or another process. `message` is the
+    /// address of the message to send or of the buffer to receive into.
+    fn block_current_with_message(
+        &mut self,
+        object: WaitObject,
+        frame: u32,
+        timeout: Option<u64>,
+        message: u32,
+    ) {
+        self.block_current(object, frame, timeout);
+        if let Some(wait) = self.current_pcb().and_then(|pcb| pcb.wait.as_mut()) {
+            wait.message = message;
+        }
+    }
+
+    /// Returns the first process in the wait queue of the object.
+    fn first_waiter(&mut self, object: WaitObject) -> Option<&mut ProcessControlBlock> {
+        self.processes
+            .iter_mut()
+            .flatten()
+            .filter(|pcb| pcb.is_blocked_on(object))
+            .min_by_key(|pcb| {
+                let sequence = pcb.wait.map_or(0, |wait| wait.sequence);
+                (Reverse(pcb.priority), sequence)
+            })
+    }
+
+    /// Wakes up the first process in the wait queue of the object and passes it the result of
+    /// its supervisor call. Returns the pid of the woken process, or [None] if no process waits
+    /// for the object.
+    fn wake_waiter(&mut self, object: WaitObject, result: SvcResult) -> Option<usize> {
+        self.first_waiter(object).map(|pcb| {
+            pcb.complete_wait(result);
+            pcb.pid
+        })
+    }
+
+    /// Converts the duration into ticks of the system timer, rounded up to at least one tick.
+    fn duration_to_ticks(&self, duration: Duration) -> u64 {
+        let cycles_per_tick = self.policy.cycles_per_tick() as u64;
+        duration.as_cycles().div_ceil(cycles_per_tick).max(1)
+    }
+
+    /// Measures the clock cycles of every context switch with the cycle counter of the
+    /// [DataWatchpointTrace].
+    #[allow(dead_code)]
+    pub fn set_cycle_counter(&mut self, mut dwt: DataWatchpointTrace) {
+        dwt.enable_cycle_counter();
+        self.cycle_counter = Some(dwt);
+    }
+
+    /// Returns the current value of the cycle counter, if one is set.
+    pub(super) fn cycle_count(&mut self) -> Option<u32> {
+        self.cycle_counter.as_mut().map(|dwt| dwt.cycle_count())
+    }
+
+    /// Records the clock cycles of a context switch that started at the cycle count `start`.
+    pub(super) fn record_switch_cycles(&mut self, start: u32) {
+        if let Some(end) = self.cycle_count() {
+            let cycles = end.wrapping_sub(start);
+            self.switch_cycles.last = cycles;
+            self.switch_cycles.max = self.switch_cycles.max.max(cycles);
+        }
+    }
+
+    /// Returns the measured clock cycles of the context switches. Stays zero unless a cycle
+    /// counter is set with [set_cycle_counter][Scheduler::set_cycle_counter].
+    #[allow(dead_code)]
+    pub fn switch_cycles(&self) -> SwitchCycles {
+        self.switch_cycles
+    }
+
+    fn current_pcb(&mut self) -> Option<&mut ProcessControlBlock> {
+        let current_pid = self.current_pid?;
+        self.processes.get_mut(current_pid)?.as_mut()
+    }
+
+    /// This function will start the scheduling of the created processes. The system timer
+    /// is configured with the time slice of the [SchedulerPolicy] and the first process gets
+    /// selected within the PendSV exception.
     ///
     /// Additionally the pointer to the scheduler will be stored in a mutable static to be
-    /// referenced in the [scheduler_task] to initiate [Policy].
+    /// referenced in the exceptions.
     pub fn start_scheduling(&mut self) -> ! {
         unsafe { SCHEDULER_REF = Some(&mut *(self as *mut Scheduler)) };
-        if let Ok(()) = self.prepare_switch_to_pid(0) {
-            trigger_PendSV();
-        }
+        enable_fault_exceptions();
+
+        self.cycles_per_tick = self.policy.cycles_per_tick();
+        self.system_timer
+            .set_reload(self.cycles_per_tick - 1)
+            .clear_val()
+            .tickint(true)
+            .enable();
+
+        trigger_PendSV();
         // unreachable
         loop {}
     }
 
-    /// All process will be created relative to [PROCESS_BASE] and are distant by [PROCESS_MEMORY_SIZE].
+    /// Selects the next process according to the [SchedulerPolicy] and prepares the
+    /// [ContextSwitch][super::cs::ContextSwitch] from the current process directly to it.
+    /// Falls back to the idle task (pid0) when no other process is ready. Gets called by the
+    /// PendSV exception.
+    ///
+    /// # Returns
+    ///
+    /// * `true` when a context switch is prepared.
+    /// * `false` when the current process keeps running.
+    pub(super) fn schedule(&mut self) -> bool {
+        self.release_current_process();
+
+        // The policy selects the first of the processes it ranks equally, starting its search
+        // at the process to switch to directly.
+        let last_pid = match self.direct_switch.take() {
+            Some(pid) if pid != 0 => pid - 1,
+            _ => self.last_pid,
+        };
+        let next_pid = self
+            .policy
+            .select_pid(&self.processes, last_pid)
+            .unwrap_or(0);
+        if next_pid != 0 {
+            self.last_pid = next_pid;
+        }
+
+        let switch = if self.current_pid == Some(next_pid) {
+            if let Some(current_pcb) = self.current_pcb() {
+                current_pcb.state = ProcessState::Running;
+            }
+            false
+        } else {
+            self.prepare_switch_to_pid(next_pid).is_ok()
+        };
+
+        // The context of a faulted process is discarded by now.
+        self.restart_faulted_processes();
+        switch
+    }
+
+    /// Creates a process with its own stack within the stack region defined in `link.x`.
+    /// Slots and stacks of terminated processes are reused.
     ///
     /// # Arguments
     ///
     /// * A process that is defined as a function with no parameters that does not return.
+    /// * The stack size in bytes. Must be at least [STACK_SIZE_MIN] and will be rounded up
+    ///   to a multiple of [STACK_ALIGN].
+    /// * The priority of the process. Higher values mean higher priority. Only considered by
+    ///   the [FixedPriority][SchedulerPolicy::FixedPriority] policy.
+    /// * Whether the process runs privileged. Only trusted processes, e.g. drivers accessing
+    ///   peripherals directly, should be privileged. All others run unprivileged.
     ///
     /// # Returns
     ///
     /// * [Ok] creation of process was successful.
     /// * [Err] with an [SchedulerError].
-    pub fn create_process(&mut self, init_fn: fn() -> !) -> Result<usize, SchedulerError> {
-        if let Some((pid, empty_slot)) = self
-            .processes
-            .iter_mut()
-            .enumerate()
-            .find(|(_, process_frame)| process_frame.is_none())
-        {
-            let init_stack_frame = unsafe {
-                &mut *((PROCESS_BASE - (pid as u32 * PROCESS_MEMORY_SIZE))
-                    as *mut InitialStackFrame)
-            };
+    pub fn create_process(
+        &mut self,
+        init_fn: fn() -> !,
+        stack_size: u32,
+        priority: u8,
+        privileged: bool,
+    ) -> Result<usize, SchedulerError> {
+        self.init_process(
+            init_fn as usize as u32,
+            0,
+            stack_size,
+            priority,
+            privileged,
+            None,
+        )
+    }
 
-            *init_stack_frame = InitialStackFrame {
-                load_stack: LoadStackFrame::default(),
-                exception_stack: ExceptionFrame::default(init_fn),
-            };
+    /// Creates a process while the scheduler is running. Gets called by the supervisor call
+    /// [Spawn][super::svc::SvcRequest::Spawn]. See [create_process][Scheduler::create_process].
+    ///
+    /// The new process inherits the privilege of the current process, so an unprivileged
+    /// process can not spawn a privileged one. Its priority must not exceed the own priority
+    /// of the current process, without one inherited through a mutex.
+    ///
+    /// # Returns
+    ///
+    /// * [Err] with [SchedulerError::PermissionDenied] if `priority` is higher than the
+    ///   priority of the current process.
+    pub(super) fn spawn(
+        &mut self,
+        init_fn: fn() -> !,
+        stack_size: u32,
+        priority: u8,
+    ) -> Result<usize, SchedulerError> {
+        let (privileged, base_priority) = match self.current_pcb() {
+            Some(pcb) => (pcb.privileged, pcb.base_priority),
+            None => (false, 0),
+        };
+        if priority > base_priority {
+            return Err(SchedulerError::PermissionDenied);
+        }
+        self.create_process(init_fn, stack_size, priority, privileged)
+    }
 
-            let auto_stack_addr = ptr::addr_of_mut!(init_stack_frame.exception_stack.r0);
+    /// Same as [create_process][Scheduler::create_process], but the function gets `arg` passed
+    /// on start. This allows several processes to share the same function.
+    pub fn create_process_with_arg(
+        &mut self,
+        init_fn: fn(usize) -> !,
+        arg: usize,
+        stack_size: u32,
+        priority: u8,
+        privileged: bool,
+    ) -> Result<usize, SchedulerError> {
+        self.init_process(
+            init_fn as usize as u32,
+            arg as u32,
+            stack_size,
+            priority,
+            privileged,
+            None,
+        )
+    }
 
-            *empty_slot = Some(ProcessControlBlock::init(
-                pid,
-                auto_stack_addr as u32,
-                ProcessState::Ready,
-            ));
+    /// Same as [create_process_with_arg][Scheduler::create_process_with_arg], but passes a
+    /// reference to a static value instead of a plain number.
+    pub fn create_process_with_ref<T: Sync>(
+        &mut self,
+        init_fn: fn(&'static T) -> !,
+        arg: &'static T,
+        stack_size: u32,
+        priority: u8,
+        privileged: bool,
+    ) -> Result<usize, SchedulerError> {
+        self.init_process(
+            init_fn as usize as u32,
+            arg as *const T as u32,
+            stack_size,
+            priority,
+            privileged,
+            None,
+        )
+    }
 
-            Ok(pid)
-        } else {
-            Err(SchedulerError::ProcessStackFull)
+    /// Same as [create_process][Scheduler::create_process], but for a process that returns.
+    /// Returning from the function terminates the process with exit code 0.
+    #[allow(dead_code)]
+    pub fn create_worker(
+        &mut self,
+        init_fn: fn(),
+        stack_size: u32,
+        priority: u8,
+        privileged: bool,
+    ) -> Result<usize, SchedulerError> {
+        self.init_process(
+            init_fn as usize as u32,
+            0,
+            stack_size,
+            priority,
+            privileged,
+            None,
+        )
+    }
+
+    /// Creates a process that gets released every `period` ticks of the system timer. Every
+    /// job has to be finished within `deadline` ticks after its release. A job is finished
+    /// when the process yields. Periodic processes are meant to be scheduled by the
+    /// [Edf][SchedulerPolicy::Edf] policy.
+    ///
+    /// # Arguments
+    ///
+    /// * A process that is defined as a function with no parameters that does not return.
+    /// * The stack size in bytes.
+    /// * The period in ticks.
+    /// * The relative deadline in ticks. Must not be greater than the period.
+    /// * Whether the process runs privileged.
+    ///
+    /// # Returns
+    ///
+    /// * [Ok] creation of process was successful.
+    /// * [Err] with an [SchedulerError].
+    #[allow(dead_code)]
+    pub fn create_periodic_process(
+        &mut self,
+        init_fn: fn() -> !,
+        stack_size: u32,
+        period: u32,
+        deadline: u32,
+        privileged: bool,
+    ) -> Result<usize, SchedulerError> {
+        if period == 0 || deadline == 0 || deadline > period {
+            return Err(SchedulerError::InvalidPeriod);
+        }
+        let periodic = PeriodicTask::init(period, deadline, self.ticks);
+        self.init_process(
+            init_fn as usize as u32,
+            0,
+            stack_size,
+            0,
+            privileged,
+            Some(periodic),
+        )
+    }
+
+    fn init_process(
+        &mut self,
+        entry: u32,
+        arg: u32,
+        stack_size: u32,
+        priority: u8,
+        privileged: bool,
+        periodic: Option<PeriodicTask>,
+    ) -> Result<usize, SchedulerError> {
+        if stack_size < STACK_SIZE_MIN {
+            return Err(SchedulerError::InvalidStackSize);
         }
+        let stack_size = (stack_size + STACK_ALIGN - 1) & !(STACK_ALIGN - 1);
+        #[cfg(feature = "mpu-isolation")]
+        let stack_size = stack_size.next_power_of_two();
+
+        let pid = self.free_slot(stack_size)?;
+        let stack = match self.processes[pid] {
+            Some(pcb) if pcb.stack.size >= stack_size => pcb.stack,
+            Some(pcb) => self.reallocate_stack(pcb.stack, stack_size)?,
+            None => self.allocate_stack(stack_size)?,
+        };
+        let psp = stack.prepare(entry, arg);
+
+        let mut pcb = ProcessControlBlock::init(pid, psp, ProcessState::Ready, priority, stack);
+        pcb.entry = entry;
+        pcb.arg = arg;
+        pcb.privileged = privileged;
+        pcb.periodic = periodic;
+        self.processes[pid] = Some(pcb);
+
+        Ok(pid)
+    }
+
+    /// Returns the pid of a free slot. Slots of terminated processes whose stack is large
+    /// enough are preferred, so their stack can be reused. A terminated process with a stack
+    /// too small only gives up its slot, if its stack is the lowest one allocated and can be
+    /// returned to the stack region, see [reallocate_stack][Scheduler::reallocate_stack].
+    fn free_slot(&self, stack_size: u32) -> Result<usize, SchedulerError> {
+        let is_terminated = |slot: &Option<ProcessControlBlock>| match slot {
+            Some(pcb) => pcb.state == ProcessState::Terminated && !pcb.restart_pending,
+            None => false,
+        };
+
+        self.processes
+            .iter()
+            .position(|slot| is_terminated(slot) && slot.unwrap().stack.size >= stack_size)
+            .or_else(|| self.processes.iter().position(|slot| slot.is_none()))
+            .or_else(|| {
+                self.processes.iter().position(|slot| {
+                    is_terminated(slot) && slot.unwrap().stack.bottom == self.stacks_free_top
+                })
+            })
+            .ok_or(SchedulerError::ProcessStackFull)
+    }
+
+    /// Allocates a new stack from the top of the stack region downwards. With the feature
+    /// `mpu-isolation` the stack is aligned to its size, so it fits into a single MPU region.
+    fn allocate_stack(&mut self, size: u32) -> Result<Stack, SchedulerError> {
+        let stacks_start = unsafe { &_sstacks as *const u8 as u32 };
+        #[cfg(not(feature = "mpu-isolation"))]
+        let align = STACK_ALIGN;
+        #[cfg(feature = "mpu-isolation")]
+        let align = size;
+
+        let bottom = match self.stacks_free_top.checked_sub(size) {
+            Some(bottom) if bottom & !(align - 1) >= stacks_start => bottom & !(align - 1),
+            _ => return Err(SchedulerError::StackRegionExhausted),
+        };
+        self.stacks_free_top = bottom;
+
+        Ok(Stack { bottom, size })
+    }
+
+    /// Returns the lowest allocated stack to the stack region and allocates a new stack in its
+    /// place. The old stack is kept, if the new one does not fit.
+    fn reallocate_stack(&mut self, old: Stack, size: u32) -> Result<Stack, SchedulerError> {
+        if old.bottom != self.stacks_free_top {
+            return Err(SchedulerError::ProcessStackFull);
+        }
+        self.stacks_free_top = old.top();
+        let stack = self.allocate_stack(size);
+        if stack.is_err() {
+            self.stacks_free_top = old.bottom;
+        }
+        stack
     }
 
     /// Prepares the [ContextSwitch][super::cs::ContextSwitch]. Returning [Ok] allows for enabling PendSV
-    /// to switch to the prepared process.
+    /// to switch to the prepared process. The context of the current process gets saved,
+    /// unless it is terminated.
     ///
     /// # Arguments
     ///
@@ -202,47 +1934,283 @@ impl Scheduler {
             None => return Err(SchedulerError::NotAvailable),
         };
 
-        let psp_next_addr = match next_process {
+        let (psp_next_addr, privileged) = match next_process {
             Some(next_pcb) => match next_pcb.state {
                 ProcessState::Ready => {
                     next_pcb.state = ProcessState::Running;
-                    ptr::addr_of_mut!(next_pcb.psp) as u32
+                    (ptr::addr_of_mut!(next_pcb.psp) as u32, next_pcb.privileged)
                 }
                 ProcessState::Running => return Err(SchedulerError::AlreadyRunning),
+                _ => return Err(SchedulerError::NotAvailable),
             },
             None => return Err(SchedulerError::NotInitialized),
         };
 
-        unsafe {
-            CONTEXT_SWITCH.set_next_addr(psp_next_addr);
-        }
-
-        if let Some(current_pid) = self.current_pid {
-            if let Some(current_pcb) = self.processes.get_mut(current_pid).unwrap() {
-                current_pcb.state = ProcessState::Ready;
+        let psp_from_addr = match self.current_pcb() {
+            Some(current_pcb) if current_pcb.state != ProcessState::Terminated => {
+                ptr::addr_of_mut!(current_pcb.psp) as u32
             }
-        }
+            _ => 0,
+        };
+
+        let context_switch = unsafe { &mut *ptr::addr_of_mut!(CONTEXT_SWITCH) };
+        context_switch.set_from_addr(psp_from_addr);
+        context_switch.set_next_addr(psp_next_addr);
+        context_switch.set_next_privileged(privileged);
+
         self.current_pid = Some(pid);
 
         Ok(())
     }
+
+    /// Sets the state of the current process back to [ProcessState::Ready], unless it went
+    /// to sleep, got blocked or terminated.
+    fn release_current_process(&mut self) {
+        if let Some(current_pcb) = self.current_pcb() {
+            if current_pcb.state == ProcessState::Running {
+                current_pcb.state = ProcessState::Ready;
+            }
+        }
+    }
+}
+
+/// Clock cycles of the context switches, measured from entering PendSV until the next
+/// process is selected and its registers are about to be loaded.
+///
+/// Not done yet: the cycles of the direct switch have not been compared with the former switch
+/// through pid0. No numbers were taken on the target for either of them, so the kernel makes
+/// no claim that the direct switch is faster. These counters are meant to take them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SwitchCycles {
+    /// Cycles of the latest context switch.
+    pub last: u32,
+    /// Maximum cycles of all context switches so far.
+    pub max: u32,
+}
+
+/// Copies a [Message][crate::kernel::ipc::Message] between the stacks of two processes.
+///
+/// # Safety
+///
+/// Both addresses have to be checked for the processes, see
+/// [is_accessible][Scheduler::is_accessible].
+unsafe fn copy_message(from: u32, to: u32) {
+    ptr::copy_nonoverlapping(from as *const u8, to as *mut u8, MESSAGE_SIZE as usize);
 }
 
 /// Every process has an [PCB][ProcessControlBlock].
 ///
 /// It holds the saved process stack pointer (psp), as well as the program id (pid).
-/// Furthermore it saves the [ProcessState].
+/// Furthermore it saves the [ProcessState], the priority including one inherited through a
+/// mutex, the mutexes it holds, whether the process runs privileged, the [Stack] of the
+/// process, the tick to wake up at, the supervisor call it is blocked in, the timing of a
+/// periodic process, the exit code of a terminated process and with the feature
+/// `mpu-isolation` the memory regions granted to the process. The entry function and its
+/// argument are kept to restart the process according to its [RestartPolicy].
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ProcessControlBlock {
     psp: u32,
     pid: usize,
     state: ProcessState,
+    priority: u8,
+    /// Priority given on creation, without an inherited one.
+    base_priority: u8,
+    /// Bit n is set while the process owns the mutex n.
+    held_mutexes: u32,
+    privileged: bool,
+    stack: Stack,
+    wake_up: Option<u64>,
+    wait: Option<Wait>,
+    periodic: Option<PeriodicTask>,
+    exit_code: Option<u32>,
+    #[cfg(feature = "mpu-isolation")]
+    granted_regions: [Option<MemoryRegion>; GRANTED_REGIONS],
+    entry: u32,
+    arg: u32,
+    restart_policy: RestartPolicy,
+    restart_pending: bool,
+    restarts: u32,
 }
 
 impl ProcessControlBlock {
-    pub fn init(pid: usize, psp: u32, state: ProcessState) -> ProcessControlBlock {
-        ProcessControlBlock { pid, psp, state }
+    pub fn init(
+        pid: usize,
+        psp: u32,
+        state: ProcessState,
+        priority: u8,
+        stack: Stack,
+    ) -> ProcessControlBlock {
+        ProcessControlBlock {
+            pid,
+            psp,
+            state,
+            priority,
+            base_priority: priority,
+            held_mutexes: 0,
+            privileged: false,
+            stack,
+            wake_up: None,
+            wait: None,
+            periodic: None,
+            exit_code: None,
+            #[cfg(feature = "mpu-isolation")]
+            granted_regions: [None; GRANTED_REGIONS],
+            entry: 0,
+            arg: 0,
+            restart_policy: RestartPolicy::Never,
+            restart_pending: false,
+            restarts: 0,
+        }
+    }
+
+    fn terminate(&mut self, exit_code: Option<u32>) {
+        self.state = ProcessState::Terminated;
+        self.wake_up = None;
+        self.wait = None;
+        self.exit_code = exit_code;
+        self.restart_pending = false;
+    }
+
+    fn is_blocked_on(&self, object: WaitObject) -> bool {
+        self.state == ProcessState::Blocked && self.wait.map(|wait| wait.object) == Some(object)
+    }
+
+    /// Makes the blocked process ready and writes the result of its blocking supervisor call
+    /// to the stacked r0 to r2.
+    fn complete_wait(&mut self, result: SvcResult) {
+        if let Some(wait) = self.wait.take() {
+            let frame = unsafe { &mut *(wait.frame as *mut ExceptionFrame) };
+            let [r0, r1, r2] = result.encode();
+            frame.r0 = r0;
+            frame.r1 = r1;
+            frame.r2 = r2;
+        }
+        self.state = ProcessState::Ready;
+        self.wake_up = None;
+    }
+
+    fn is_ready(&self) -> bool {
+        self.state == ProcessState::Ready
+    }
+
+    /// Wakes up a sleeping process, or a blocked one whose timeout has expired. Returns `true`
+    /// when the timeout of a blocked process expired.
+    fn wake_up_if_due(&mut self, ticks: u64) -> bool {
+        match self.wake_up {
+            Some(wake_up) if wake_up <= ticks => match self.state {
+                ProcessState::Sleeping => {
+                    self.state = ProcessState::Ready;
+                    self.wake_up = None;
+                    false
+                }
+                ProcessState::Blocked => {
+                    self.complete_wait(SvcResult::Error(SysError::Timeout));
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// The memory area of a process stack within the stack region.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stack {
+    /// Lowest address of the stack.
+    bottom: u32,
+    /// Size in bytes.
+    size: u32,
+}
+
+impl Stack {
+    /// The stack grows downwards from this address.
+    fn top(&self) -> u32 {
+        self.bottom + self.size
+    }
+
+    fn write_canary(&self) {
+        unsafe { (self.bottom as *mut u32).write_volatile(STACK_CANARY) };
+    }
+
+    fn is_canary_intact(&self) -> bool {
+        unsafe { (self.bottom as *const u32).read_volatile() == STACK_CANARY }
+    }
+
+    /// Words of the stack that are painted and measured. Leaves out the area that might be
+    /// guarded by the MPU.
+    fn measured_words(&self) -> impl Iterator<Item = *mut u32> {
+        (self.bottom + REGION_SIZE_MIN..self.top())
+            .step_by(4)
+            .map(|addr| addr as *mut u32)
+    }
+
+    /// Writes the canary, paints the stack and places the [InitialStackFrame] on top.
+    /// Returns the psp to be loaded on the first context switch.
+    fn prepare(&self, entry: u32, arg: u32) -> u32 {
+        self.write_canary();
+        self.paint();
+
+        let init_stack_frame = unsafe {
+            &mut *((self.top() - size_of::<InitialStackFrame>() as u32) as *mut InitialStackFrame)
+        };
+
+        *init_stack_frame = InitialStackFrame {
+            load_stack: LoadStackFrame::default(),
+            exception_stack: ExceptionFrame::default(entry, arg),
+        };
+
+        ptr::addr_of_mut!(init_stack_frame.load_stack.r4) as u32
+    }
+
+    /// Paints the stack with [STACK_PAINT] up to the [InitialStackFrame].
+    fn paint(&self) {
+        let frame_addr = self.top() - size_of::<InitialStackFrame>() as u32;
+        self.measured_words()
+            .take_while(|word| (*word as u32) < frame_addr)
+            .for_each(|word| unsafe { word.write_volatile(STACK_PAINT) });
+    }
+
+    /// Scans from the bottom for the first word that is not painted anymore.
+    fn high_water_mark(&self) -> u32 {
+        match self
+            .measured_words()
+            .find(|word| unsafe { word.read_volatile() } != STACK_PAINT)
+        {
+            Some(word) => self.top() - word as u32,
+            None => 0,
+        }
+    }
+}
+
+/// Timing of a periodic process. All values are in ticks of the system timer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeriodicTask {
+    period: u32,
+    deadline: u32,
+    release: u64,
+    absolute_deadline: u64,
+}
+
+impl PeriodicTask {
+    fn init(period: u32, deadline: u32, release: u64) -> PeriodicTask {
+        PeriodicTask {
+            period,
+            deadline,
+            release,
+            absolute_deadline: release + deadline as u64,
+        }
+    }
+
+    /// Advances to the next job and returns its release tick.
+    ///
+    /// A job that misses its deadline is not aborted. Its successor will be released as
+    /// soon as it is finished.
+    fn next_job(&mut self) -> u64 {
+        self.release += self.period as u64;
+        self.absolute_deadline = self.release + self.deadline as u64;
+        self.release
     }
 }
 
@@ -260,7 +2228,11 @@ pub struct InitialStackFrame {
 /// Will be initially loaded when the first context switch occurs.
 ///
 /// It needs to have an align_buffer to be placed correctly on top
-/// of the 8 byte aligned [ExceptionFrame].
+/// of the 8 byte aligned [ExceptionFrame]. The registers are loaded directly below it.
+///
+/// The lr holds EXC_RETURN. `0xfffffffd` returns to thread mode using the psp with a basic
+/// frame, as a new process has not used the FPU yet. Once a process uses the FPU, its saved
+/// context additionally contains s16-s31 between lr and the extended exception frame.
 #[repr(C)]
 pub struct LoadStackFrame {
     _align_buffer: [u32; 7],
@@ -296,33 +2268,33 @@ impl LoadStackFrame {
 /// corresponding process.
 ///
 /// It needs to be 8 bytes aligned. Initially, the PC will hold the reference to
-/// the function of the process task.
+/// the function of the process task, the LR to [process_return] and R0 the argument
+/// passed to the process.
 ///
 /// *NOTE: This will be only needed to be handled once, after that the processor will
 /// automatically create an auto stack frame each time an exception occurs.*
 #[repr(C, align(8))]
 pub struct ExceptionFrame {
-    r0: u32,
-    r1: u32,
-    r2: u32,
-    r3: u32,
-    r12: u32,
-    lr: u32,
-    pc: u32,
-    xpsr: u32,
+    pub(super) r0: u32,
+    pub(super) r1: u32,
+    pub(super) r2: u32,
+    pub(super) r3: u32,
+    pub(super) r12: u32,
+    pub(super) lr: u32,
+    pub(super) pc: u32,
+    pub(super) xpsr: u32,
 }
 
 impl ExceptionFrame {
-    fn default(init_fn: fn() -> !) -> ExceptionFrame {
-        let init_fn = ptr::addr_of!(init_fn);
+    fn default(entry: u32, arg: u32) -> ExceptionFrame {
         ExceptionFrame {
-            r0: 0,
+            r0: arg,
             r1: 0,
             r2: 0,
             r3: 0,
             r12: 0,
-            lr: 0,
-            pc: unsafe { init_fn.read_unaligned() as *const () as u32 },
+            lr: process_return as fn() -> ! as usize as u32,
+            pc: entry,
             xpsr: 0x1000000,
         }
     }
@@ -1,23 +1,25 @@
 //! # Policies
 //!
+//! The policy decides which process runs next. The decision is made within the PendSV
+//! exception, which then switches directly from the preempted process to the selected one.
+//! The idle task (pid0) only runs when no other process is ready.
+//!
 //! ## RoundRobin
 //!
 //! The figure below shows how the [RoundRobin][SchedulerPolicy::RoundRobin] policy is implemented.
-//! In this case, between each user process task the scheduler task will be called to select the next
-//! process to be scheduled.
+//! Each ready process runs for one time slice of the system timer or until it yields.
 //!
 //! ```text
 //!  pid
-//! curr  0      1      0      2      0      3      0
-//! next  1      0      2      0      3      0      ...
+//! curr  1      2      3      1      2      3
+//! next  2      3      1      2      3      1      ...
 //!
-//!    0  *             *             *             *
-//!    1         *       
-//!    2                       *
-//!    3                                     *
+//!    1  *                    *
+//!    2         *                    *
+//!    3                *                    *
 //! etc.
-//!       m      t      m      t      m      t      m
-//!       |______|______|______|______|______|______|_... -> time axis
+//!       t      m      t      t      m      t
+//!       |______|______|______|______|______|_... -> time axis
 //!
 //!
 //! PendSV trigger:
@@ -25,24 +27,59 @@
 //! t = triggered from timer
 //!
 //! ```
+//!
+//! ## FixedPriority
+//!
+//! The [FixedPriority][SchedulerPolicy::FixedPriority] policy always selects the ready process
+//! with the highest priority. Processes sharing the same priority are scheduled round robin,
+//! each of them for one time slice of the system timer. In the figure below pid1 and pid2 have
+//! a higher priority than pid3, so pid3 is not selected as long as the others are ready.
+//!
+//! ```text
+//!  pid  prio
+//!    1   2   *             *
+//!    2   2          *             *
+//!    3   1
+//!        |______|______|______|______|_... -> time axis
+//! ```
+//!
+//! ## Edf
+//!
+//! The [Edf][SchedulerPolicy::Edf] policy (earliest deadline first) always selects the ready job
+//! with the nearest absolute deadline. A periodic process is released every period and yields
+//! once its job is done. Afterwards it will not be selected until its next release. Given that
+//! every relative deadline equals its period, all deadlines are met as long as the utilisation
+//! `U = sum(C_i / T_i)` does not exceed 1, with `C_i` being the execution time and `T_i` the
+//! period of each process in ticks.
+//!
+//! ```text
+//!  pid  T  D
+//!    1  4  2  *                 *
+//!    2  6  6        *                 *
+//!    3  -  -              *                 *
+//!             |_____|_____|_____|_____|_____|_... -> time axis
+//! ```
+//!
+//! ## Direct switch
+//!
+//! A process sending a message to a process waiting in receive hands over to it, and a reply
+//! hands back to the client, see [ipc][crate::kernel::ipc]. The policy then starts its search
+//! at that process, so it is selected, unless the policy ranks another ready process higher.
 use crate::{
     cp::stk::STK_RELOAD_MAX,
     kernel::{
-        cs::CONTEXT_SWITCH,
-        exceptions::trigger_PendSV,
-        scheduler::{Scheduler, ALLOWED_PROCESSES},
+        scheduler::{ProcessControlBlock, ALLOWED_PROCESSES},
+        time::Duration,
     },
 };
 
-#[cfg(feature = "semihosting")]
-use crate::kernel::sprint;
-
-use core::ptr;
+use core::cmp::Reverse;
 
-/// Minimum switch rate in clock cycle, that ensures that the scheduler does not jump
-/// back too early. This translates to 1 ms execution time (0x1F40 * 125 ns).
-const SWITCH_RATE_CC_MIN: u32 = 0x1F40;
+/// Minimum switch rate in clock cycles, that ensures that the scheduler does not jump
+/// back too early. This translates to 1 ms execution time.
+const SWITCH_RATE_CC_MIN: u32 = Duration::from_millis(1).as_cycles() as u32;
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum SchedulerPolicy {
     /// RoundRobin with optional custom context switch rate in clock cycles.
@@ -50,54 +87,97 @@ pub enum SchedulerPolicy {
     /// Must be between [SWITCH_RATE_CC_MIN] and [STK_RELOAD_MAX].
     /// Default is [SWITCH_RATE_CC_MIN].
     RoundRobin(Option<u32>),
+    /// Preemptive scheduling by the priority given in [create_process][super::Scheduler::create_process].
+    /// Processes with equal priority are scheduled round robin with an optional custom context
+    /// switch rate in clock cycles.
+    ///
+    /// Must be between [SWITCH_RATE_CC_MIN] and [STK_RELOAD_MAX].
+    /// Default is [SWITCH_RATE_CC_MIN].
+    FixedPriority(Option<u32>),
+    /// Earliest deadline first with optional custom tick rate in clock cycles. Periodic processes
+    /// are created with [create_periodic_process][super::Scheduler::create_periodic_process]. Of all
+    /// ready jobs the one with the nearest absolute deadline runs. Non-periodic processes only
+    /// run round robin when no periodic job is ready.
+    ///
+    /// Must be between [SWITCH_RATE_CC_MIN] and [STK_RELOAD_MAX].
+    /// Default is [SWITCH_RATE_CC_MIN].
+    Edf(Option<u32>),
 }
 
-#[derive(Debug)]
-pub(super) struct Policy {
-    scheduler: &'static mut Scheduler,
-}
+impl SchedulerPolicy {
+    /// Returns the length of a tick of the system timer in clock cycles, which is the length
+    /// of a time slice.
+    pub(super) fn cycles_per_tick(&self) -> u32 {
+        let cc_switch_rate_custom = match self {
+            SchedulerPolicy::RoundRobin(cc_switch_rate_custom) => cc_switch_rate_custom,
+            SchedulerPolicy::FixedPriority(cc_switch_rate_custom) => cc_switch_rate_custom,
+            SchedulerPolicy::Edf(cc_switch_rate_custom) => cc_switch_rate_custom,
+        };
 
-impl Policy {
-    /// Excepts a pointer to the initialized Scheduler.
-    pub fn init(scheduler: &'static mut Scheduler) -> Policy {
-        let pid0 = scheduler.processes[0].as_mut().unwrap();
-        unsafe {
-            CONTEXT_SWITCH.set_from_addr(ptr::addr_of!(pid0.psp) as u32);
+        match cc_switch_rate_custom {
+            Some(cc_switch_rate)
+                if (SWITCH_RATE_CC_MIN..STK_RELOAD_MAX).contains(cc_switch_rate) =>
+            {
+                *cc_switch_rate
+            }
+            _ => SWITCH_RATE_CC_MIN,
         }
-        Policy { scheduler }
     }
 
-    /// Does not return. Will execute the selected policy.
-    pub fn schedule(self) -> ! {
-        match self.scheduler.policy {
-            SchedulerPolicy::RoundRobin(cc_switch_rate_custom) => {
-                let mut reload_val = SWITCH_RATE_CC_MIN;
-                if let Some(cc_switch_rate) = cc_switch_rate_custom {
-                    if (SWITCH_RATE_CC_MIN..STK_RELOAD_MAX).contains(&cc_switch_rate) {
-                        reload_val = cc_switch_rate;
-                    }
-                }
-
-                let mut cycle = (1..ALLOWED_PROCESSES).cycle();
+    /// Selects the next process to run among the user processes. Returns [None] when no
+    /// process is ready.
+    pub(super) fn select_pid(
+        &self,
+        processes: &[Option<ProcessControlBlock>; ALLOWED_PROCESSES],
+        last_pid: usize,
+    ) -> Option<usize> {
+        match self {
+            SchedulerPolicy::RoundRobin(_) => select_lowest(processes, last_pid, |_| ()),
+            SchedulerPolicy::FixedPriority(_) => {
+                select_lowest(processes, last_pid, |pcb| Reverse(pcb.priority))
+            }
+            SchedulerPolicy::Edf(_) => {
+                select_lowest(processes, last_pid, |pcb| match pcb.periodic {
+                    Some(periodic) => periodic.absolute_deadline,
+                    None => u64::MAX,
+                })
+            }
+        }
+    }
+}
 
-                self.scheduler
-                    .system_timer
-                    .set_reload(reload_val)
-                    .clear_val()
-                    .tickint(true);
+/// Returns the pid of the ready process with the lowest key. Processes that are not
+/// [Ready][super::ProcessState::Ready] are skipped.
+///
+/// The search starts right after `last_pid`, therefore processes with the same key take
+/// turns. [RoundRobin][SchedulerPolicy::RoundRobin] uses the same key for every process,
+/// [FixedPriority][SchedulerPolicy::FixedPriority] the reversed priority and
+/// [Edf][SchedulerPolicy::Edf] the absolute deadline. Non-periodic processes are treated
+/// as if they had no deadline at all.
+fn select_lowest<K: Ord>(
+    processes: &[Option<ProcessControlBlock>; ALLOWED_PROCESSES],
+    last_pid: usize,
+    key: fn(&ProcessControlBlock) -> K,
+) -> Option<usize> {
+    let user_processes = ALLOWED_PROCESSES - 1;
+    let mut selected: Option<(usize, K)> = None;
 
-                loop {
-                    if let Some(pid) = cycle.next() {
-                        if let Ok(()) = self.scheduler.prepare_switch_to_pid(pid) {
-                            #[cfg(feature = "semihosting")]
-                            sprint("context switch\n");
-                            self.scheduler.system_timer.enable();
-                            trigger_PendSV();
-                            self.scheduler.system_timer.disable().clear_val();
-                        }
-                    }
-                }
+    for offset in 0..user_processes {
+        let pid = (last_pid + offset) % user_processes + 1;
+        if let Some(pcb) = &processes[pid] {
+            if !pcb.is_ready() {
+                continue;
+            }
+            let pcb_key = key(pcb);
+            let is_lower = match &selected {
+                Some((_, selected_key)) => pcb_key < *selected_key,
+                None => true,
+            };
+            if is_lower {
+                selected = Some((pid, pcb_key));
             }
         }
     }
+
+    selected.map(|(pid, _)| pid)
 }
@@ -0,0 +1,80 @@
+//! # Semaphores
+//!
+//! A semaphore holds a count of available units up to a maximum. A binary semaphore has a
+//! maximum of one. Waiting takes a unit. A process waiting for an unavailable unit is blocked
+//! and put into the wait queue of the semaphore, so the policies do not select it until a unit
+//! is posted or its timeout expires. Posting hands the unit directly to the waiting process
+//! with the highest priority, first come, first served among equal priorities.
+//!
+//! Interrupt handlers post with [post_from_interrupt], as they can not use supervisor calls.
+
+use super::{
+    exceptions::trigger_PendSV,
+    interrupt_free,
+    scheduler::{scheduler_ref, SchedulerError},
+    svc::SysError,
+};
+
+/// Maximum number of semaphores.
+pub const MAX_SEMAPHORES: usize = 8;
+
+/// Identifies a semaphore.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SemaphoreId(pub(super) usize);
+
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Semaphore {
+    count: u32,
+    max: u32,
+}
+
+impl Semaphore {
+    /// Returns [None] unless `max` is at least one and `initial` does not exceed it.
+    pub(super) fn new(initial: u32, max: u32) -> Option<Semaphore> {
+        if max == 0 || initial > max {
+            return None;
+        }
+        Some(Semaphore {
+            count: initial,
+            max,
+        })
+    }
+
+    /// Takes a unit. Returns `false` if none is available.
+    pub(super) fn try_take(&mut self) -> bool {
+        if self.count == 0 {
+            return false;
+        }
+        self.count -= 1;
+        true
+    }
+
+    /// Returns a unit. A post exceeding the maximum count is ignored.
+    pub(super) fn give(&mut self) {
+        self.count = (self.count + 1).min(self.max);
+    }
+}
+
+/// Posts the semaphore from an interrupt handler. A process woken up by the post gets
+/// scheduled once the interrupt has returned.
+///
+/// The interrupt has to keep the default priority, so it does not preempt the kernel. The
+/// post itself runs with interrupts disabled, so other interrupts can not interleave with it.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::InvalidArgument] if there is no such semaphore or scheduling has
+///   not started yet.
+#[allow(dead_code)]
+pub fn post_from_interrupt(id: SemaphoreId) -> Result<(), SysError> {
+    interrupt_free(|| {
+        let scheduler = match unsafe { scheduler_ref() } {
+            Some(scheduler) => scheduler,
+            None => return Err(SchedulerError::NoSuchSemaphore.into()),
+        };
+        if scheduler.post_semaphore(id)? {
+            trigger_PendSV();
+        }
+        Ok(())
+    })
+}
@@ -1,16 +1,89 @@
 //! Supervisor Call (System Calls)
 //!
+//! A system call passes its number in r12 and up to four arguments in r0 to r3. The kernel
+//! reads them from the exception frame stacked by the caller and writes the result back to the
+//! stacked r0 to r2. Failed system calls return a [SysError]. Pointers are checked against the
+//! memory of the calling process, before the kernel accesses them.
 
-use super::__syscall;
+use super::{
+    __syscall,
+    event::{EventGroupId, WaitMode},
+    ipc::{Message, MESSAGE_WORDS},
+    mutex::MutexId,
+    queue::QueueId,
+    scheduler::SchedulerError,
+    semaphore::SemaphoreId,
+    time::{Duration, Instant},
+    timer::TimerId,
+};
+use core::mem::transmute;
 
-/// Maximum length of text being written to the console. Last 
+/// Maximum length of text being written to the console. Last
 /// character will be overwritten to be null-terminated.
 #[cfg(feature = "semihosting")]
 const SEMIHOSTING_WRITE_LENGTH: usize = 64;
 
+/// Numbers of the supervisor calls, passed in r12. They are the same regardless of the
+/// enabled features.
+#[cfg(feature = "semihosting")]
+const SVC_SEMIHOSTING_WRITE0: u32 = 0x01;
+#[cfg(feature = "semihosting")]
+const SVC_SEMIHOSTING_WRITEC: u32 = 0x02;
+#[cfg(feature = "semihosting")]
+const SVC_SEMIHOSTING_READC: u32 = 0x03;
+const SVC_YIELD: u32 = 0x10;
+const SVC_SLEEP: u32 = 0x11;
+const SVC_SLEEP_UNTIL: u32 = 0x12;
+const SVC_GET_TIME: u32 = 0x13;
+const SVC_EXIT: u32 = 0x20;
+const SVC_KILL: u32 = 0x21;
+const SVC_STACK_USAGE: u32 = 0x22;
+const SVC_SPAWN: u32 = 0x23;
+const SVC_TIMER_CREATE: u32 = 0x30;
+const SVC_TIMER_WAIT: u32 = 0x31;
+const SVC_TIMER_CANCEL: u32 = 0x32;
+const SVC_SEM_CREATE: u32 = 0x40;
+const SVC_SEM_WAIT: u32 = 0x41;
+const SVC_SEM_TRY_WAIT: u32 = 0x42;
+const SVC_SEM_POST: u32 = 0x43;
+const SVC_MUTEX_CREATE: u32 = 0x50;
+const SVC_MUTEX_LOCK: u32 = 0x51;
+const SVC_MUTEX_TRY_LOCK: u32 = 0x52;
+const SVC_MUTEX_UNLOCK: u32 = 0x53;
+const SVC_QUEUE_CREATE: u32 = 0x60;
+const SVC_QUEUE_SEND: u32 = 0x61;
+const SVC_QUEUE_TRY_SEND: u32 = 0x62;
+const SVC_QUEUE_RECEIVE: u32 = 0x63;
+const SVC_QUEUE_TRY_RECEIVE: u32 = 0x64;
+const SVC_EVENT_CREATE: u32 = 0x70;
+const SVC_EVENT_SET: u32 = 0x71;
+const SVC_EVENT_CLEAR: u32 = 0x72;
+const SVC_EVENT_WAIT: u32 = 0x73;
+const SVC_IPC_SEND: u32 = 0x80;
+const SVC_IPC_RECEIVE: u32 = 0x81;
+const SVC_IPC_REPLY: u32 = 0x82;
+
+/// Options of [SvcRequest::EventWait], passed in the upper half of r0 next to the id.
+const EVENT_WAIT_ALL: u32 = 1 << 16;
+const EVENT_WAIT_CLEAR: u32 = 1 << 17;
+
+/// Passed as timeout to wait without a timeout.
+const TIMEOUT_NEVER: u64 = u64::MAX;
+
+/// Variants of [SvcResult], returned in r0. The value of the variant is returned in r1 and,
+/// if it exceeds 32 bits, r2.
+const RESULT_NONE: u32 = 0;
+const RESULT_CHAR: u32 = 1;
+const RESULT_PID: u32 = 2;
+const RESULT_BYTES: u32 = 3;
+const RESULT_TICKS: u32 = 4;
+const RESULT_TIME: u32 = 5;
+const RESULT_ID: u32 = 6;
+const RESULT_FLAGS: u32 = 7;
+const RESULT_ERROR: u32 = 0xFF;
+
 /// Systemcalls requests.
 #[allow(dead_code)]
-#[repr(C)]
 pub enum SvcRequest {
     /// Writes null-terminated array of characters to console.
     #[cfg(feature = "semihosting")]
@@ -21,57 +94,869 @@ pub enum SvcRequest {
     /// Reads character from console.
     #[cfg(feature = "semihosting")]
     SemihostingReadC,
-    /// Yields process. Returns to scheduler.
+    /// Yields process. Returns to scheduler. For a periodic process this finishes
+    /// the current job.
     Yield,
+    /// Puts the process to sleep for the given amount of ticks. It is taken out of the run
+    /// queue until then.
+    Sleep(u32),
+    /// Puts the process to sleep until the kernel time reaches the given tick. Returns
+    /// immediately if the tick has already passed.
+    SleepUntil(u64),
+    /// Returns the kernel time as [Instant].
+    GetTime,
+    /// Terminates the process with the given exit code. Does not return.
+    Exit(u32),
+    /// Terminates the process with the given pid. pid0 can not be killed.
+    Kill(usize),
+    /// Returns the maximum stack usage in bytes of the process with the given pid.
+    StackUsage(usize),
+    /// Creates a new process. Returns its pid.
+    Spawn {
+        entry: fn() -> !,
+        stack_size: u32,
+        priority: u8,
+    },
+    /// Creates a timer notifying the process after the given duration, and periodically
+    /// afterwards if requested. Returns the id of the timer.
+    TimerCreate { duration: Duration, periodic: bool },
+    /// Blocks the process until the timer expires. Returns immediately if it has expired
+    /// since the last wait.
+    TimerWait(TimerId),
+    /// Removes the timer.
+    TimerCancel(TimerId),
+    /// Creates a semaphore with the given initial and maximum count. Returns the id of the
+    /// semaphore.
+    SemCreate { initial: u32, max: u32 },
+    /// Takes a unit of the semaphore. Blocks the process until a unit is posted or the
+    /// timeout, if any, expires.
+    SemWait {
+        id: SemaphoreId,
+        timeout: Option<Duration>,
+    },
+    /// Takes a unit of the semaphore without blocking.
+    SemTryWait(SemaphoreId),
+    /// Returns a unit to the semaphore, or hands it to a waiting process.
+    SemPost(SemaphoreId),
+    /// Creates a mutex, which may be locked repeatedly by its owner if recursive. Returns the
+    /// id of the mutex.
+    MutexCreate { recursive: bool },
+    /// Locks the mutex. Blocks the process while another process owns it, until the timeout,
+    /// if any, expires.
+    MutexLock {
+        id: MutexId,
+        timeout: Option<Duration>,
+    },
+    /// Locks the mutex without blocking.
+    MutexTryLock(MutexId),
+    /// Unlocks the mutex owned by the process.
+    MutexUnlock(MutexId),
+    /// Creates a message queue for the given number of messages of the given size in bytes.
+    /// Returns the id of the queue.
+    QueueCreate { message_size: u32, capacity: u32 },
+    /// Copies the message into the queue. Blocks the process while the queue is full, until
+    /// the timeout, if any, expires.
+    QueueSend {
+        id: QueueId,
+        message: *const u8,
+        timeout: Option<Duration>,
+    },
+    /// Copies the message into the queue without blocking.
+    QueueTrySend { id: QueueId, message: *const u8 },
+    /// Moves the oldest message of the queue into the buffer. Blocks the process while the
+    /// queue is empty, until the timeout, if any, expires.
+    QueueReceive {
+        id: QueueId,
+        buffer: *mut u8,
+        timeout: Option<Duration>,
+    },
+    /// Moves the oldest message of the queue into the buffer without blocking.
+    QueueTryReceive { id: QueueId, buffer: *mut u8 },
+    /// Creates an event flag group with all flags cleared. Returns the id of the group.
+    EventCreate,
+    /// Sets the flags of the group.
+    EventSet { id: EventGroupId, flags: u32 },
+    /// Clears the flags of the group.
+    EventClear { id: EventGroupId, flags: u32 },
+    /// Waits for any or all flags of the mask. Blocks the process until they are set or the
+    /// timeout, if any, expires. Returns the flags of the group meeting the condition, before
+    /// they are cleared if requested.
+    EventWait {
+        id: EventGroupId,
+        mask: u32,
+        mode: WaitMode,
+        clear: bool,
+        timeout: Option<Duration>,
+    },
+    /// Sends the message to the process with the given pid. Blocks the process until the
+    /// message is received and replied to.
+    IpcSend {
+        pid: usize,
+        message: *const Message,
+        reply: *mut Message,
+    },
+    /// Receives a message into the buffer. Blocks the process until a message is sent to it.
+    /// Returns the pid of the sender.
+    IpcReceive(*mut Message),
+    /// Replies to the process with the given pid, whose message was received.
+    IpcReply { pid: usize, message: *const Message },
 }
 
-/// A system call will write the result as an [SvcResult] variant.
-#[repr(C)]
+impl SvcRequest {
+    /// Encodes the request into its number and up to four arguments, which are passed in the
+    /// registers r12 and r0 to r3.
+    fn encode(&self) -> (u32, [u32; 4]) {
+        match *self {
+            #[cfg(feature = "semihosting")]
+            SvcRequest::SemihostingWrite0(text) => (SVC_SEMIHOSTING_WRITE0, [text as u32, 0, 0, 0]),
+            #[cfg(feature = "semihosting")]
+            SvcRequest::SemihostingWriteC(char) => (SVC_SEMIHOSTING_WRITEC, [char as u32, 0, 0, 0]),
+            #[cfg(feature = "semihosting")]
+            SvcRequest::SemihostingReadC => (SVC_SEMIHOSTING_READC, [0; 4]),
+            SvcRequest::Yield => (SVC_YIELD, [0; 4]),
+            SvcRequest::Sleep(ticks) => (SVC_SLEEP, [ticks, 0, 0, 0]),
+            SvcRequest::SleepUntil(tick) => {
+                (SVC_SLEEP_UNTIL, [tick as u32, (tick >> 32) as u32, 0, 0])
+            }
+            SvcRequest::GetTime => (SVC_GET_TIME, [0; 4]),
+            SvcRequest::Exit(exit_code) => (SVC_EXIT, [exit_code, 0, 0, 0]),
+            SvcRequest::Kill(pid) => (SVC_KILL, [pid as u32, 0, 0, 0]),
+            SvcRequest::StackUsage(pid) => (SVC_STACK_USAGE, [pid as u32, 0, 0, 0]),
+            SvcRequest::Spawn {
+                entry,
+                stack_size,
+                priority,
+            } => (
+                SVC_SPAWN,
+                [entry as usize as u32, stack_size, priority as u32, 0],
+            ),
+            SvcRequest::TimerCreate { duration, periodic } => {
+                let cycles = duration.as_cycles();
+                (
+                    SVC_TIMER_CREATE,
+                    [cycles as u32, (cycles >> 32) as u32, periodic as u32, 0],
+                )
+            }
+            SvcRequest::TimerWait(id) => (SVC_TIMER_WAIT, [id.0 as u32, 0, 0, 0]),
+            SvcRequest::TimerCancel(id) => (SVC_TIMER_CANCEL, [id.0 as u32, 0, 0, 0]),
+            SvcRequest::SemCreate { initial, max } => (SVC_SEM_CREATE, [initial, max, 0, 0]),
+            SvcRequest::SemWait { id, timeout } => {
+                let [low, high] = encode_timeout(timeout);
+                (SVC_SEM_WAIT, [id.0 as u32, low, high, 0])
+            }
+            SvcRequest::SemTryWait(id) => (SVC_SEM_TRY_WAIT, [id.0 as u32, 0, 0, 0]),
+            SvcRequest::SemPost(id) => (SVC_SEM_POST, [id.0 as u32, 0, 0, 0]),
+            SvcRequest::MutexCreate { recursive } => {
+                (SVC_MUTEX_CREATE, [recursive as u32, 0, 0, 0])
+            }
+            SvcRequest::MutexLock { id, timeout } => {
+                let [low, high] = encode_timeout(timeout);
+                (SVC_MUTEX_LOCK, [id.0 as u32, low, high, 0])
+            }
+            SvcRequest::MutexTryLock(id) => (SVC_MUTEX_TRY_LOCK, [id.0 as u32, 0, 0, 0]),
+            SvcRequest::MutexUnlock(id) => (SVC_MUTEX_UNLOCK, [id.0 as u32, 0, 0, 0]),
+            SvcRequest::QueueCreate {
+                message_size,
+                capacity,
+            } => (SVC_QUEUE_CREATE, [message_size, capacity, 0, 0]),
+            SvcRequest::QueueSend {
+                id,
+                message,
+                timeout,
+            } => {
+                let [low, high] = encode_timeout(timeout);
+                (SVC_QUEUE_SEND, [id.0 as u32, message as u32, low, high])
+            }
+            SvcRequest::QueueTrySend { id, message } => {
+                (SVC_QUEUE_TRY_SEND, [id.0 as u32, message as u32, 0, 0])
+            }
+            SvcRequest::QueueReceive {
+                id,
+                buffer,
+                timeout,
+            } => {
+                let [low, high] = encode_timeout(timeout);
+                (SVC_QUEUE_RECEIVE, [id.0 as u32, buffer as u32, low, high])
+            }
+            SvcRequest::QueueTryReceive { id, buffer } => {
+                (SVC_QUEUE_TRY_RECEIVE, [id.0 as u32, buffer as u32, 0, 0])
+            }
+            SvcRequest::EventCreate => (SVC_EVENT_CREATE, [0; 4]),
+            SvcRequest::EventSet { id, flags } => (SVC_EVENT_SET, [id.0 as u32, flags, 0, 0]),
+            SvcRequest::EventClear { id, flags } => (SVC_EVENT_CLEAR, [id.0 as u32, flags, 0, 0]),
+            SvcRequest::EventWait {
+                id,
+                mask,
+                mode,
+                clear,
+                timeout,
+            } => {
+                let mut id_options = id.0 as u32 & 0xFFFF;
+                if mode == WaitMode::All {
+                    id_options |= EVENT_WAIT_ALL;
+                }
+                if clear {
+                    id_options |= EVENT_WAIT_CLEAR;
+                }
+                let [low, high] = encode_timeout(timeout);
+                (SVC_EVENT_WAIT, [id_options, mask, low, high])
+            }
+            SvcRequest::IpcSend {
+                pid,
+                message,
+                reply,
+            } => (SVC_IPC_SEND, [pid as u32, message as u32, reply as u32, 0]),
+            SvcRequest::IpcReceive(buffer) => (SVC_IPC_RECEIVE, [buffer as u32, 0, 0, 0]),
+            SvcRequest::IpcReply { pid, message } => {
+                (SVC_IPC_REPLY, [pid as u32, message as u32, 0, 0])
+            }
+        }
+    }
+
+    /// Decodes the request from the number and the arguments read from the stacked exception
+    /// frame of the caller.
+    ///
+    /// # Returns
+    ///
+    /// * [None] for an unknown number or a null entry function.
+    pub(super) fn decode(number: u32, args: [u32; 4]) -> Option<SvcRequest> {
+        let request = match number {
+            #[cfg(feature = "semihosting")]
+            SVC_SEMIHOSTING_WRITE0 => SvcRequest::SemihostingWrite0(args[0] as *const u8),
+            #[cfg(feature = "semihosting")]
+            SVC_SEMIHOSTING_WRITEC => SvcRequest::SemihostingWriteC(args[0] as *const u8),
+            #[cfg(feature = "semihosting")]
+            SVC_SEMIHOSTING_READC => SvcRequest::SemihostingReadC,
+            SVC_YIELD => SvcRequest::Yield,
+            SVC_SLEEP => SvcRequest::Sleep(args[0]),
+            SVC_SLEEP_UNTIL => SvcRequest::SleepUntil(args[0] as u64 | (args[1] as u64) << 32),
+            SVC_GET_TIME => SvcRequest::GetTime,
+            SVC_EXIT => SvcRequest::Exit(args[0]),
+            SVC_KILL => SvcRequest::Kill(args[0] as usize),
+            SVC_STACK_USAGE => SvcRequest::StackUsage(args[0] as usize),
+            SVC_SPAWN if args[0] != 0 => SvcRequest::Spawn {
+                // A function pointer must not be null, which is checked above.
+                entry: unsafe { transmute::<usize, fn() -> !>(args[0] as usize) },
+                stack_size: args[1],
+                priority: args[2] as u8,
+            },
+            SVC_TIMER_CREATE => SvcRequest::TimerCreate {
+                duration: Duration::from_cycles(args[0] as u64 | (args[1] as u64) << 32),
+                periodic: args[2] != 0,
+            },
+            SVC_TIMER_WAIT => SvcRequest::TimerWait(TimerId(args[0] as usize)),
+            SVC_TIMER_CANCEL => SvcRequest::TimerCancel(TimerId(args[0] as usize)),
+            SVC_SEM_CREATE => SvcRequest::SemCreate {
+                initial: args[0],
+                max: args[1],
+            },
+            SVC_SEM_WAIT => SvcRequest::SemWait {
+                id: SemaphoreId(args[0] as usize),
+                timeout: decode_timeout([args[1], args[2]]),
+            },
+            SVC_SEM_TRY_WAIT => SvcRequest::SemTryWait(SemaphoreId(args[0] as usize)),
+            SVC_SEM_POST => SvcRequest::SemPost(SemaphoreId(args[0] as usize)),
+            SVC_MUTEX_CREATE => SvcRequest::MutexCreate {
+                recursive: args[0] != 0,
+            },
+            SVC_MUTEX_LOCK => SvcRequest::MutexLock {
+                id: MutexId(args[0] as usize),
+                timeout: decode_timeout([args[1], args[2]]),
+            },
+            SVC_MUTEX_TRY_LOCK => SvcRequest::MutexTryLock(MutexId(args[0] as usize)),
+            SVC_MUTEX_UNLOCK => SvcRequest::MutexUnlock(MutexId(args[0] as usize)),
+            SVC_QUEUE_CREATE => SvcRequest::QueueCreate {
+                message_size: args[0],
+                capacity: args[1],
+            },
+            SVC_QUEUE_SEND => SvcRequest::QueueSend {
+                id: QueueId(args[0] as usize),
+                message: args[1] as *const u8,
+                timeout: decode_timeout([args[2], args[3]]),
+            },
+            SVC_QUEUE_TRY_SEND => SvcRequest::QueueTrySend {
+                id: QueueId(args[0] as usize),
+                message: args[1] as *const u8,
+            },
+            SVC_QUEUE_RECEIVE => SvcRequest::QueueReceive {
+                id: QueueId(args[0] as usize),
+                buffer: args[1] as *mut u8,
+                timeout: decode_timeout([args[2], args[3]]),
+            },
+            SVC_QUEUE_TRY_RECEIVE => SvcRequest::QueueTryReceive {
+                id: QueueId(args[0] as usize),
+                buffer: args[1] as *mut u8,
+            },
+            SVC_EVENT_CREATE => SvcRequest::EventCreate,
+            SVC_EVENT_SET => SvcRequest::EventSet {
+                id: EventGroupId(args[0] as usize),
+                flags: args[1],
+            },
+            SVC_EVENT_CLEAR => SvcRequest::EventClear {
+                id: EventGroupId(args[0] as usize),
+                flags: args[1],
+            },
+            SVC_EVENT_WAIT => SvcRequest::EventWait {
+                id: EventGroupId((args[0] & 0xFFFF) as usize),
+                mask: args[1],
+                mode: match args[0] & EVENT_WAIT_ALL {
+                    0 => WaitMode::Any,
+                    _ => WaitMode::All,
+                },
+                clear: args[0] & EVENT_WAIT_CLEAR != 0,
+                timeout: decode_timeout([args[2], args[3]]),
+            },
+            SVC_IPC_SEND => SvcRequest::IpcSend {
+                pid: args[0] as usize,
+                message: args[1] as *const Message,
+                reply: args[2] as *mut Message,
+            },
+            SVC_IPC_RECEIVE => SvcRequest::IpcReceive(args[0] as *mut Message),
+            SVC_IPC_REPLY => SvcRequest::IpcReply {
+                pid: args[0] as usize,
+                message: args[1] as *const Message,
+            },
+            _ => return None,
+        };
+        Some(request)
+    }
+}
+
+/// Encodes the timeout of a blocking request into two registers. [None] waits forever and is
+/// passed as [TIMEOUT_NEVER].
+fn encode_timeout(timeout: Option<Duration>) -> [u32; 2] {
+    let cycles = timeout.map_or(TIMEOUT_NEVER, |timeout| timeout.as_cycles());
+    [cycles as u32, (cycles >> 32) as u32]
+}
+
+fn decode_timeout(registers: [u32; 2]) -> Option<Duration> {
+    match registers[0] as u64 | (registers[1] as u64) << 32 {
+        TIMEOUT_NEVER => None,
+        cycles => Some(Duration::from_cycles(cycles)),
+    }
+}
+
+/// A system call will write the result as an [SvcResult] variant. The typed wrappers, e.g.
+/// [kill] or [spawn], convert it into a [Result].
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SvcResult {
     None,
     Char(u8),
+    Pid(usize),
+    Bytes(u32),
+    Ticks(u64),
+    Time(Instant),
+    /// Identifies a kernel object, e.g. a timer.
+    Id(usize),
+    /// Flags of an event flag group.
+    Flags(u32),
+    Error(SysError),
+}
+
+impl SvcResult {
+    /// Encodes the result into the registers r0 to r2, which are written back to the stacked
+    /// exception frame of the caller.
+    pub(super) fn encode(self) -> [u32; 3] {
+        match self {
+            SvcResult::None => [RESULT_NONE, 0, 0],
+            SvcResult::Char(char) => [RESULT_CHAR, char as u32, 0],
+            SvcResult::Pid(pid) => [RESULT_PID, pid as u32, 0],
+            SvcResult::Bytes(bytes) => [RESULT_BYTES, bytes, 0],
+            SvcResult::Ticks(ticks) => [RESULT_TICKS, ticks as u32, (ticks >> 32) as u32],
+            SvcResult::Time(instant) => {
+                let cycles = instant.as_cycles();
+                [RESULT_TIME, cycles as u32, (cycles >> 32) as u32]
+            }
+            SvcResult::Id(id) => [RESULT_ID, id as u32, 0],
+            SvcResult::Flags(flags) => [RESULT_FLAGS, flags, 0],
+            SvcResult::Error(error) => [RESULT_ERROR, error as u32, 0],
+        }
+    }
+
+    /// Decodes the result returned by a supervisor call in r0 to r2.
+    fn decode(registers: [u32; 3]) -> SvcResult {
+        let [variant, low, high] = registers;
+        match variant {
+            RESULT_NONE => SvcResult::None,
+            RESULT_CHAR => SvcResult::Char(low as u8),
+            RESULT_PID => SvcResult::Pid(low as usize),
+            RESULT_BYTES => SvcResult::Bytes(low),
+            RESULT_TICKS => SvcResult::Ticks(low as u64 | (high as u64) << 32),
+            RESULT_TIME => SvcResult::Time(Instant::from_cycles(low as u64 | (high as u64) << 32)),
+            RESULT_ID => SvcResult::Id(low as usize),
+            RESULT_FLAGS => SvcResult::Flags(low),
+            RESULT_ERROR => SvcResult::Error(SysError::from_code(low)),
+            _ => SvcResult::Error(SysError::InvalidArgument),
+        }
+    }
+
+    /// Returns [Ok] for [SvcResult::None].
+    pub(super) fn into_unit(self) -> Result<(), SysError> {
+        match self {
+            SvcResult::None => Ok(()),
+            other => Err(other.into_error()),
+        }
+    }
+
+    /// Returns the error of [SvcResult::Error]. Any other unexpected variant means the request
+    /// was not understood.
+    pub(super) fn into_error(self) -> SysError {
+        match self {
+            SvcResult::Error(error) => error,
+            _ => SysError::InvalidArgument,
+        }
+    }
 }
 
-/// The [SvcOrder] is a helper struct for system calls. The order itself is
-/// allocated on the calling process stack. The SVCall exceptions then uses
-/// a pointer to evaluate the request and possible write any result back to
-/// the order.
+/// Errors returned by system calls.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u32)]
+pub enum SysError {
+    /// An argument is out of range or the system call is unknown.
+    InvalidArgument = 1,
+    /// There is no process with the given pid.
+    NoSuchProcess = 2,
+    /// The request can not be completed without blocking, e.g. by a try variant.
+    WouldBlock = 3,
+    /// The request could not be completed within the given timeout.
+    Timeout = 4,
+    /// The calling process is not allowed to perform the request, e.g. a pointer refers to
+    /// memory it has no access to.
+    PermissionDenied = 5,
+    /// The kernel has no resources left, e.g. no free process slot or stack memory.
+    OutOfResources = 6,
+    /// The request would never complete, e.g. locking a non-recursive mutex twice.
+    Deadlock = 7,
+}
+
+impl SysError {
+    /// Returns the error for the code returned by a supervisor call. An unknown code is
+    /// returned as [SysError::InvalidArgument].
+    fn from_code(code: u32) -> SysError {
+        match code {
+            2 => SysError::NoSuchProcess,
+            3 => SysError::WouldBlock,
+            4 => SysError::Timeout,
+            5 => SysError::PermissionDenied,
+            6 => SysError::OutOfResources,
+            7 => SysError::Deadlock,
+            _ => SysError::InvalidArgument,
+        }
+    }
+}
+
+impl From<SchedulerError> for SysError {
+    fn from(error: SchedulerError) -> SysError {
+        match error {
+            SchedulerError::NotInitialized | SchedulerError::NotAvailable => {
+                SysError::NoSuchProcess
+            }
+            SchedulerError::ProcessStackFull
+            | SchedulerError::StackRegionExhausted
+            | SchedulerError::RegionsExhausted
+            | SchedulerError::TimersExhausted
+            | SchedulerError::SemaphoresExhausted
+            | SchedulerError::MutexesExhausted
+            | SchedulerError::QueuesExhausted
+            | SchedulerError::EventGroupsExhausted => SysError::OutOfResources,
+            SchedulerError::NoSuchTimer
+            | SchedulerError::NoSuchSemaphore
+            | SchedulerError::NoSuchMutex
+            | SchedulerError::NoSuchQueue
+            | SchedulerError::NoSuchEventGroup
+            | SchedulerError::InvalidFlags
+            | SchedulerError::NotWaitingForReply
+            | SchedulerError::InvalidCount
+            | SchedulerError::AlreadyRunning
+            | SchedulerError::InvalidPeriod
+            | SchedulerError::InvalidStackSize => SysError::InvalidArgument,
+            SchedulerError::WouldBlock => SysError::WouldBlock,
+            SchedulerError::NotOwner | SchedulerError::PermissionDenied => {
+                SysError::PermissionDenied
+            }
+            SchedulerError::Deadlock => SysError::Deadlock,
+        }
+    }
+}
+
+/// Registers passed to and returned from [__syscall]. r0 to r3 hold the arguments and r12
+/// the number of the supervisor call. Afterwards r0 to r2 hold the result.
 #[repr(C)]
-pub struct SvcOrder {
-    pub request: SvcRequest,
-    pub response: SvcResult,
+pub(super) struct SvcRegisters {
+    r0: u32,
+    r1: u32,
+    r2: u32,
+    r3: u32,
+    r12: u32,
 }
 
-/// Will trigger a system call. After the system call was executed, the
-/// result will be in [SvcResult].
+/// Will trigger a system call. The request is passed in registers, see [SvcRequest::encode].
+/// After the system call was executed, the result will be in [SvcResult].
 pub fn syscall(request: SvcRequest) -> SvcResult {
-    let order = SvcOrder {
-        request,
-        response: SvcResult::None,
+    let (number, args) = request.encode();
+    let mut registers = SvcRegisters {
+        r0: args[0],
+        r1: args[1],
+        r2: args[2],
+        r3: args[3],
+        r12: number,
+    };
+    unsafe { __syscall(&mut registers) };
+    SvcResult::decode([registers.r0, registers.r1, registers.r2])
+}
+
+/// Yields the calling process. For a periodic process this finishes the current job.
+#[allow(dead_code)]
+pub fn yield_now() -> Result<(), SysError> {
+    syscall(SvcRequest::Yield).into_unit()
+}
+
+/// Puts the calling process to sleep for the given amount of ticks.
+#[allow(dead_code)]
+pub fn sleep(ticks: u32) -> Result<(), SysError> {
+    syscall(SvcRequest::Sleep(ticks)).into_unit()
+}
+
+/// Puts the calling process to sleep until the kernel time reaches the given tick.
+#[allow(dead_code)]
+pub fn sleep_until(tick: u64) -> Result<(), SysError> {
+    syscall(SvcRequest::SleepUntil(tick)).into_unit()
+}
+
+/// Returns the kernel time.
+#[allow(dead_code)]
+pub fn now() -> Result<Instant, SysError> {
+    match syscall(SvcRequest::GetTime) {
+        SvcResult::Time(instant) => Ok(instant),
+        other => Err(other.into_error()),
+    }
+}
+
+/// Terminates the process with the given pid.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::PermissionDenied] for pid0, or if the calling process is
+///   unprivileged and `pid` is not its own.
+/// * [Err] with [SysError::NoSuchProcess] if there is no such process.
+#[allow(dead_code)]
+pub fn kill(pid: usize) -> Result<(), SysError> {
+    syscall(SvcRequest::Kill(pid)).into_unit()
+}
+
+/// Returns the maximum stack usage in bytes of the process with the given pid.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::PermissionDenied] if the calling process is unprivileged and `pid`
+///   is not its own.
+#[allow(dead_code)]
+pub fn stack_usage(pid: usize) -> Result<u32, SysError> {
+    match syscall(SvcRequest::StackUsage(pid)) {
+        SvcResult::Bytes(bytes) => Ok(bytes),
+        other => Err(other.into_error()),
+    }
+}
+
+/// Creates a new process, which inherits the privilege of the calling process. Returns its pid.
+/// Only privileged processes may spawn.
+///
+/// The priority of the new process is limited to the own priority of the calling process, so
+/// a process can not take the processor from processes it does not outrank itself.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::InvalidArgument] if the stack size is too small.
+/// * [Err] with [SysError::OutOfResources] if there is no slot or stack memory left.
+/// * [Err] with [SysError::PermissionDenied] if the calling process is unprivileged, may not
+///   execute `entry` or `priority` is higher than its own priority.
+#[allow(dead_code)]
+pub fn spawn(entry: fn() -> !, stack_size: u32, priority: u8) -> Result<usize, SysError> {
+    let request = SvcRequest::Spawn {
+        entry,
+        stack_size,
+        priority,
+    };
+    match syscall(request) {
+        SvcResult::Pid(pid) => Ok(pid),
+        other => Err(other.into_error()),
+    }
+}
+
+/// Creates a timer notifying the calling process after `duration`, and every `duration`
+/// afterwards if `periodic`. Wait for it with [timer_wait]. A one-shot timer is removed once
+/// its expiry has been waited for, it does not have to be cancelled.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::OutOfResources] if all timers are in use.
+#[allow(dead_code)]
+pub fn timer_create(duration: Duration, periodic: bool) -> Result<TimerId, SysError> {
+    match syscall(SvcRequest::TimerCreate { duration, periodic }) {
+        SvcResult::Id(id) => Ok(TimerId(id)),
+        other => Err(other.into_error()),
+    }
+}
+
+/// Blocks the calling process until the timer expires.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::InvalidArgument] if there is no such timer of the calling process
+///   or it gets cancelled while waiting.
+#[allow(dead_code)]
+pub fn timer_wait(id: TimerId) -> Result<(), SysError> {
+    syscall(SvcRequest::TimerWait(id)).into_unit()
+}
+
+/// Removes the timer.
+#[allow(dead_code)]
+pub fn timer_cancel(id: TimerId) -> Result<(), SysError> {
+    syscall(SvcRequest::TimerCancel(id)).into_unit()
+}
+
+/// Creates a semaphore with `initial` units available and at most `max` units. A binary
+/// semaphore has a maximum of one.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::InvalidArgument] if `max` is zero or less than `initial`.
+/// * [Err] with [SysError::OutOfResources] if all semaphores are in use.
+#[allow(dead_code)]
+pub fn sem_create(initial: u32, max: u32) -> Result<SemaphoreId, SysError> {
+    match syscall(SvcRequest::SemCreate { initial, max }) {
+        SvcResult::Id(id) => Ok(SemaphoreId(id)),
+        other => Err(other.into_error()),
+    }
+}
+
+/// Takes a unit of the semaphore. Blocks the calling process until a unit is available, or at
+/// most for `timeout` if given.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::Timeout] if no unit was posted within the timeout.
+/// * [Err] with [SysError::InvalidArgument] if there is no such semaphore.
+#[allow(dead_code)]
+pub fn sem_wait(id: SemaphoreId, timeout: Option<Duration>) -> Result<(), SysError> {
+    syscall(SvcRequest::SemWait { id, timeout }).into_unit()
+}
+
+/// Takes a unit of the semaphore if one is available.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::WouldBlock] if no unit is available.
+#[allow(dead_code)]
+pub fn sem_try_wait(id: SemaphoreId) -> Result<(), SysError> {
+    syscall(SvcRequest::SemTryWait(id)).into_unit()
+}
+
+/// Returns a unit to the semaphore. Interrupt handlers use
+/// [post_from_interrupt][super::semaphore::post_from_interrupt] instead.
+#[allow(dead_code)]
+pub fn sem_post(id: SemaphoreId) -> Result<(), SysError> {
+    syscall(SvcRequest::SemPost(id)).into_unit()
+}
+
+/// Creates a mutex. The owner of a `recursive` mutex may lock it repeatedly and has to unlock
+/// it as often.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::OutOfResources] if all mutexes are in use.
+#[allow(dead_code)]
+pub fn mutex_create(recursive: bool) -> Result<MutexId, SysError> {
+    match syscall(SvcRequest::MutexCreate { recursive }) {
+        SvcResult::Id(id) => Ok(MutexId(id)),
+        other => Err(other.into_error()),
+    }
+}
+
+/// Locks the mutex. Blocks the calling process while another process owns it, or at most for
+/// `timeout` if given. The owner inherits the priority of the calling process meanwhile, if
+/// it is higher.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::Timeout] if the mutex was not handed over within the timeout.
+/// * [Err] with [SysError::Deadlock] if the calling process owns the non-recursive mutex
+///   already.
+/// * [Err] with [SysError::InvalidArgument] if there is no such mutex.
+#[allow(dead_code)]
+pub fn mutex_lock(id: MutexId, timeout: Option<Duration>) -> Result<(), SysError> {
+    syscall(SvcRequest::MutexLock { id, timeout }).into_unit()
+}
+
+/// Locks the mutex if no other process owns it.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::WouldBlock] if another process owns the mutex.
+#[allow(dead_code)]
+pub fn mutex_try_lock(id: MutexId) -> Result<(), SysError> {
+    syscall(SvcRequest::MutexTryLock(id)).into_unit()
+}
+
+/// Unlocks the mutex.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::PermissionDenied] if the calling process does not own the mutex.
+#[allow(dead_code)]
+pub fn mutex_unlock(id: MutexId) -> Result<(), SysError> {
+    syscall(SvcRequest::MutexUnlock(id)).into_unit()
+}
+
+/// Creates an event flag group with all flags cleared.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::OutOfResources] if all groups are in use.
+#[allow(dead_code)]
+pub fn event_create() -> Result<EventGroupId, SysError> {
+    match syscall(SvcRequest::EventCreate) {
+        SvcResult::Id(id) => Ok(EventGroupId(id)),
+        other => Err(other.into_error()),
+    }
+}
+
+/// Sets the flags of the group, waking up every process waiting for them. Interrupt handlers
+/// use [set_from_interrupt][super::event::set_from_interrupt] instead.
+#[allow(dead_code)]
+pub fn event_set(id: EventGroupId, flags: u32) -> Result<(), SysError> {
+    syscall(SvcRequest::EventSet { id, flags }).into_unit()
+}
+
+/// Clears the flags of the group. Interrupt handlers use
+/// [clear_from_interrupt][super::event::clear_from_interrupt] instead.
+#[allow(dead_code)]
+pub fn event_clear(id: EventGroupId, flags: u32) -> Result<(), SysError> {
+    syscall(SvcRequest::EventClear { id, flags }).into_unit()
+}
+
+/// Waits for any or all flags of `mask`, depending on `mode`. Blocks the calling process until
+/// they are set, or at most for `timeout` if given. The flags of `mask` are cleared afterwards
+/// if `clear` is set.
+///
+/// # Returns
+///
+/// * [Ok] with the flags of the group at the time the condition was met.
+/// * [Err] with [SysError::Timeout] if the condition was not met within the timeout.
+/// * [Err] with [SysError::WouldBlock] for a zero timeout and a condition not met.
+/// * [Err] with [SysError::InvalidArgument] if `mask` is empty or there is no such group.
+#[allow(dead_code)]
+pub fn event_wait(
+    id: EventGroupId,
+    mask: u32,
+    mode: WaitMode,
+    clear: bool,
+    timeout: Option<Duration>,
+) -> Result<u32, SysError> {
+    let request = SvcRequest::EventWait {
+        id,
+        mask,
+        mode,
+        clear,
+        timeout,
     };
+    match syscall(request) {
+        SvcResult::Flags(flags) => Ok(flags),
+        other => Err(other.into_error()),
+    }
+}
 
-    unsafe {
-        __syscall(&order);
+/// Sends the message to the process `pid` and blocks the calling process until `pid` has
+/// received it and replied. Returns the reply.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::NoSuchProcess] if there is no such process or it terminates
+///   before replying.
+/// * [Err] with [SysError::Deadlock] if the calling process sends to itself.
+#[allow(dead_code)]
+pub fn ipc_send(pid: usize, message: &Message) -> Result<Message, SysError> {
+    let mut reply: Message = [0; MESSAGE_WORDS];
+    let request = SvcRequest::IpcSend {
+        pid,
+        message,
+        reply: &mut reply,
     };
-    order.response
+    syscall(request).into_unit()?;
+    Ok(reply)
+}
+
+/// Blocks the calling process until a message is sent to it. Returns the pid of the sender,
+/// which waits for [ipc_reply], and the message.
+#[allow(dead_code)]
+pub fn ipc_receive() -> Result<(usize, Message), SysError> {
+    let mut message: Message = [0; MESSAGE_WORDS];
+    match syscall(SvcRequest::IpcReceive(&mut message)) {
+        SvcResult::Pid(pid) => Ok((pid, message)),
+        other => Err(other.into_error()),
+    }
+}
+
+/// Replies to the message received from the process `pid`, which continues afterwards.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::InvalidArgument] if `pid` does not wait for a reply of the calling
+///   process.
+#[allow(dead_code)]
+pub fn ipc_reply(pid: usize, message: &Message) -> Result<(), SysError> {
+    syscall(SvcRequest::IpcReply { pid, message }).into_unit()
+}
+
+/// Reads a character from the console.
+#[cfg(feature = "semihosting")]
+#[allow(dead_code)]
+pub fn read_char() -> Result<u8, SysError> {
+    match syscall(SvcRequest::SemihostingReadC) {
+        SvcResult::Char(char) => Ok(char),
+        other => Err(other.into_error()),
+    }
+}
+
+/// Writes a null-terminated string to the console.
+///
+/// # Returns
+///
+/// * [Err] with [SysError::InvalidArgument] if `text` does not contain a null.
+#[cfg(feature = "semihosting")]
+pub fn write_c_str(text: &[u8]) -> Result<(), SysError> {
+    if !text.contains(&0) {
+        return Err(SysError::InvalidArgument);
+    }
+    syscall(SvcRequest::SemihostingWrite0(text.as_ptr())).into_unit()
+}
+
+/// Writes a character to the console.
+#[cfg(feature = "semihosting")]
+#[allow(dead_code)]
+pub fn write_char(char: u8) -> Result<(), SysError> {
+    syscall(SvcRequest::SemihostingWriteC(&char)).into_unit()
+}
+
+/// Terminates the calling process with the given exit code.
+pub fn exit(exit_code: u32) -> ! {
+    syscall(SvcRequest::Exit(exit_code));
+    // unreachable
+    loop {
+        core::hint::spin_loop();
+    }
 }
 
 /// Convenient method for printing text on the console. Be aware that the
-/// length of the text is restricted by [SEMIHOSTING_WRITE_LENGTH].
+/// length of the text is restricted by [SEMIHOSTING_WRITE_LENGTH], including the
+/// terminating null. Longer text is truncated.
 #[cfg(feature = "semihosting")]
-pub fn sprint(text: &str) {
+pub fn sprint(text: &str) -> Result<(), SysError> {
     let mut whole = [0; SEMIHOSTING_WRITE_LENGTH];
-    for (index, empty_char) in whole.iter_mut().enumerate() {
-        if index == SEMIHOSTING_WRITE_LENGTH - 1 {
-            *empty_char = '\0' as u8;
-        }
-        match text.as_bytes().get(index) {
-            Some(char) => *empty_char = *char,
-            None => {
-                *empty_char = '\0' as u8;
-                break;
-            }
-        }
-    }
-    syscall(SvcRequest::SemihostingWrite0(whole.as_ptr() as *const u8));
+    let len = text.len().min(SEMIHOSTING_WRITE_LENGTH - 1);
+    whole[..len].copy_from_slice(&text.as_bytes()[..len]);
+    // The remaining bytes, at least the last one, terminate the text.
+    write_c_str(&whole)
 }
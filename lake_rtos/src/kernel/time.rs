@@ -0,0 +1,119 @@
+//! # Time
+//!
+//! The kernel time starts with the scheduling. It consists of the ticks of the system timer
+//! and the clock cycles elapsed within the current tick, read from its current value register.
+//! Both are combined into an [Instant], which counts the clock cycles of the processor.
+//!
+//! [Duration] converts between clock cycles and common units based on [CORE_CLOCK_HZ], so
+//! clock cycles do not have to be calculated by hand.
+//!
+//! ```text
+//!  tick        n-1          n          n+1
+//!   |___________|___________|_____.____|___ -> time axis
+//!                           |<--->|
+//!                        reload - stk_val
+//! ```
+
+use core::ops::{Add, Sub};
+
+/// Clock of the processor in Hz. The STM32F303 runs from its internal 8 MHz oscillator
+/// after reset.
+pub const CORE_CLOCK_HZ: u32 = 8_000_000;
+
+/// A span of time in clock cycles of the processor.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration {
+    cycles: u64,
+}
+
+#[allow(dead_code)]
+impl Duration {
+    pub const fn from_cycles(cycles: u64) -> Duration {
+        Duration { cycles }
+    }
+
+    pub const fn from_micros(micros: u64) -> Duration {
+        Duration::from_cycles(micros * CORE_CLOCK_HZ as u64 / 1_000_000)
+    }
+
+    pub const fn from_millis(millis: u64) -> Duration {
+        Duration::from_cycles(millis * CORE_CLOCK_HZ as u64 / 1_000)
+    }
+
+    pub const fn from_secs(secs: u64) -> Duration {
+        Duration::from_cycles(secs * CORE_CLOCK_HZ as u64)
+    }
+
+    pub const fn as_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Rounds down to whole microseconds.
+    pub const fn as_micros(&self) -> u64 {
+        // The product exceeds u64 after about 26 days at 8 MHz.
+        (self.cycles as u128 * 1_000_000 / CORE_CLOCK_HZ as u128) as u64
+    }
+
+    /// Rounds down to whole milliseconds.
+    pub const fn as_millis(&self) -> u64 {
+        (self.cycles as u128 * 1_000 / CORE_CLOCK_HZ as u128) as u64
+    }
+
+    /// Rounds down to whole seconds.
+    pub const fn as_secs(&self) -> u64 {
+        self.cycles / CORE_CLOCK_HZ as u64
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        Duration::from_cycles(self.cycles + other.cycles)
+    }
+}
+
+/// A point in time, measured in clock cycles since the scheduling started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant {
+    cycles: u64,
+}
+
+#[allow(dead_code)]
+impl Instant {
+    pub(super) fn from_cycles(cycles: u64) -> Instant {
+        Instant { cycles }
+    }
+
+    pub fn as_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the time elapsed since scheduling started.
+    pub fn since_start(&self) -> Duration {
+        Duration::from_cycles(self.cycles)
+    }
+
+    /// Returns the time elapsed from `earlier` to this instant. Returns zero if `earlier` is
+    /// later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_cycles(self.cycles.saturating_sub(earlier.cycles))
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant::from_cycles(self.cycles + duration.as_cycles())
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    /// See [duration_since][Instant::duration_since].
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
@@ -0,0 +1,291 @@
+//! # Software timers
+//!
+//! A timer expires after a number of ticks of the system timer, once or periodically. Armed
+//! timers are kept in a list sorted by their expiry tick, so the SysTick exception only has
+//! to look at the head of the list.
+//!
+//! ```text
+//!  head
+//!   |     expiry 12       expiry 15       expiry 40
+//!   '---> | timer 3 | --> | timer 0 | --> | timer 5 | --> None
+//! ```
+//!
+//! On expiry a timer either calls a [TimerCallback] or notifies a process, which waits for
+//! the timer with the supervisor call [TimerWait][super::svc::SvcRequest::TimerWait].
+//!
+//! A one-shot timer frees its slot once its expiry has been delivered, i.e. the callback has
+//! returned or the process has been notified. Its id is invalid afterwards. Each slot counts
+//! its generation, which is part of the [TimerId], so an old id never refers to a later timer
+//! in the same slot.
+
+/// Maximum number of timers.
+pub const MAX_TIMERS: usize = 8;
+
+/// Bits of a [TimerId] holding the slot, the remaining bits hold the generation.
+const SLOT_BITS: u32 = 8;
+
+/// Gets called with the expired timer.
+///
+/// The callback runs within the SysTick exception. Therefore, it must not use system calls
+/// and should return quickly.
+pub type TimerCallback = fn(timer: TimerId);
+
+/// Identifies a timer by its slot and the generation of the slot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimerId(pub(super) usize);
+
+impl TimerId {
+    fn new(index: usize, generation: usize) -> TimerId {
+        TimerId(generation << SLOT_BITS | index)
+    }
+
+    fn index(&self) -> usize {
+        self.0 & ((1 << SLOT_BITS) - 1)
+    }
+
+    fn generation(&self) -> usize {
+        self.0 >> SLOT_BITS
+    }
+}
+
+/// What happens when a timer expires.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum TimerTarget {
+    /// Calls the function within the SysTick exception.
+    Callback(TimerCallback),
+    /// Notifies the process with the given pid. Expiries the process has not waited for yet
+    /// are counted.
+    Process(usize),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SoftwareTimer {
+    /// Tick at which the timer expires next.
+    expiry: u64,
+    /// Ticks between two expiries of a periodic timer.
+    period: Option<u64>,
+    target: TimerTarget,
+    /// Expiries not waited for by the target process yet.
+    pending: u32,
+    /// Next armed timer in the sorted list.
+    next: Option<usize>,
+    armed: bool,
+}
+
+/// The timers and the list of the armed ones, sorted by expiry.
+#[derive(Debug)]
+pub(super) struct TimerList {
+    timers: [Option<SoftwareTimer>; MAX_TIMERS],
+    /// Generation of each slot, advanced whenever its timer is removed.
+    generations: [usize; MAX_TIMERS],
+    head: Option<usize>,
+}
+
+impl TimerList {
+    pub(super) const fn new() -> TimerList {
+        TimerList {
+            timers: [None; MAX_TIMERS],
+            generations: [0; MAX_TIMERS],
+            head: None,
+        }
+    }
+
+    /// Creates and arms a timer expiring at tick `expiry`, and every `period` ticks afterwards
+    /// if given.
+    ///
+    /// # Returns
+    ///
+    /// * [None] if all timers are in use.
+    pub(super) fn create(
+        &mut self,
+        expiry: u64,
+        period: Option<u64>,
+        target: TimerTarget,
+    ) -> Option<TimerId> {
+        let index = self.timers.iter().position(|slot| slot.is_none())?;
+        self.timers[index] = Some(SoftwareTimer {
+            expiry,
+            period,
+            target,
+            pending: 0,
+            next: None,
+            armed: false,
+        });
+        self.insert(index);
+        Some(self.id(index))
+    }
+
+    /// Removes the timer. Returns `false` if there is no such timer.
+    pub(super) fn cancel(&mut self, id: TimerId) -> bool {
+        match self.index(id) {
+            Some(index) => {
+                self.unlink(index);
+                self.free(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes every timer notifying the given process.
+    pub(super) fn cancel_targeting(&mut self, pid: usize) {
+        for index in 0..MAX_TIMERS {
+            let id = self.id(index);
+            match self.target(id) {
+                Some(TimerTarget::Process(target)) if target == pid => {
+                    self.cancel(id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub(super) fn target(&self, id: TimerId) -> Option<TimerTarget> {
+        let index = self.index(id)?;
+        self.timers[index].map(|timer| timer.target)
+    }
+
+    /// Counts an expiry the target process has not waited for yet.
+    pub(super) fn add_pending(&mut self, id: TimerId) {
+        if let Some(timer) = self.timer_mut(id) {
+            timer.pending = timer.pending.saturating_add(1);
+        }
+    }
+
+    /// Consumes a counted expiry. Returns `false` if there is none.
+    pub(super) fn take_pending(&mut self, id: TimerId) -> bool {
+        match self.timer_mut(id) {
+            Some(timer) if timer.pending > 0 => {
+                timer.pending -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes a one-shot timer that has expired, unless its expiry still waits to be
+    /// delivered to the target process.
+    pub(super) fn release_if_fired(&mut self, id: TimerId) {
+        if let Some(index) = self.index(id) {
+            match self.timers[index] {
+                Some(timer) if timer.period.is_none() && !timer.armed && timer.pending == 0 => {
+                    self.free(index);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the timer at the head of the list, if it has expired at tick `ticks`. A
+    /// periodic timer is rearmed for its next expiry, a one-shot timer stays disarmed until it
+    /// is released, see [release_if_fired][TimerList::release_if_fired].
+    pub(super) fn pop_expired(&mut self, ticks: u64) -> Option<(TimerId, TimerTarget)> {
+        let index = self.head?;
+        let timer = self.timers[index].as_mut()?;
+        if timer.expiry > ticks {
+            return None;
+        }
+        let target = timer.target;
+        let period = timer.period;
+        self.unlink(index);
+
+        if let (Some(period), Some(timer)) = (period, self.timers[index].as_mut()) {
+            timer.expiry += period;
+            self.insert(index);
+        }
+        Some((self.id(index), target))
+    }
+
+    /// Returns the id of the timer in the slot.
+    fn id(&self, index: usize) -> TimerId {
+        TimerId::new(index, self.generations[index])
+    }
+
+    /// Returns the slot of the timer, if it still exists.
+    fn index(&self, id: TimerId) -> Option<usize> {
+        let index = id.index();
+        match (self.timers.get(index), self.generations.get(index)) {
+            (Some(Some(_)), Some(&generation)) if generation == id.generation() => Some(index),
+            _ => None,
+        }
+    }
+
+    fn timer_mut(&mut self, id: TimerId) -> Option<&mut SoftwareTimer> {
+        let index = self.index(id)?;
+        self.timers[index].as_mut()
+    }
+
+    /// Empties the slot and advances its generation, which invalidates the id of the timer.
+    fn free(&mut self, index: usize) {
+        self.timers[index] = None;
+        // Wraps within the bits of a TimerId left for the generation.
+        self.generations[index] = (self.generations[index] + 1) & (usize::MAX >> SLOT_BITS);
+    }
+
+    /// Inserts the timer into the list in front of the first timer expiring later.
+    fn insert(&mut self, index: usize) {
+        let expiry = match self.timers[index] {
+            Some(timer) => timer.expiry,
+            None => return,
+        };
+
+        let mut previous: Option<usize> = None;
+        let mut current = self.head;
+        while let Some(current_index) = current {
+            match self.timers[current_index] {
+                Some(timer) if timer.expiry <= expiry => {
+                    previous = current;
+                    current = timer.next;
+                }
+                _ => break,
+            }
+        }
+
+        if let Some(timer) = self.timers[index].as_mut() {
+            timer.next = current;
+            timer.armed = true;
+        }
+        match previous {
+            Some(previous_index) => {
+                if let Some(timer) = self.timers[previous_index].as_mut() {
+                    timer.next = Some(index);
+                }
+            }
+            None => self.head = Some(index),
+        }
+    }
+
+    /// Removes the timer from the list, if it is armed.
+    fn unlink(&mut self, index: usize) {
+        let (armed, next) = match self.timers[index] {
+            Some(timer) => (timer.armed, timer.next),
+            None => return,
+        };
+        if !armed {
+            return;
+        }
+
+        if self.head == Some(index) {
+            self.head = next;
+        } else {
+            let mut current = self.head;
+            while let Some(current_index) = current {
+                let timer = match self.timers[current_index].as_mut() {
+                    Some(timer) => timer,
+                    None => break,
+                };
+                if timer.next == Some(index) {
+                    timer.next = next;
+                    break;
+                }
+                current = timer.next;
+            }
+        }
+
+        if let Some(timer) = self.timers[index].as_mut() {
+            timer.next = None;
+            timer.armed = false;
+        }
+    }
+}
@@ -21,16 +21,25 @@ use dp::{
 use driver::leds::{CardinalPoints::*, LEDs};
 use kernel::{
     scheduler::{policies::SchedulerPolicy::RoundRobin, Scheduler},
-    syscall,
-    SvcRequest::*,
-    SvcResult::*,
+    svc::yield_now,
+    time::Duration,
 };
 
 #[cfg(feature = "semihosting")]
-use kernel::sprint;
+use kernel::svc::{read_char, sprint, write_c_str};
 
-/// pid1
-fn user_task_pid_1() -> ! {
+/// Configuration passed by reference to a counter process.
+struct CounterConfig {
+    id: usize,
+}
+
+static COUNTER_CONFIG: CounterConfig = CounterConfig { id: 3 };
+
+/// Counts up and prints the counter. The argument is used as id of the counter.
+fn user_task_counter(id: usize) -> ! {
+    // Only printed over semihosting.
+    #[cfg(not(feature = "semihosting"))]
+    let _ = id;
     let mut counter: u32 = 0;
 
     loop {
@@ -38,11 +47,11 @@ fn user_task_pid_1() -> ! {
         #[cfg(feature = "semihosting")]
         {
             let display = [
-                'p' as u8,
-                'i' as u8,
-                'd' as u8,
+                'c' as u8,
+                'n' as u8,
+                't' as u8,
                 ' ' as u8,
-                '1' as u8,
+                (id % 10 + 48) as u8,
                 ' ' as u8,
                 ((counter / 1000) % 10 + 48) as u8,
                 ((counter / 100) % 10 + 48) as u8,
@@ -52,12 +61,17 @@ fn user_task_pid_1() -> ! {
                 '\0' as u8,
             ];
 
-            syscall(SemihostingWrite0(display.as_ptr() as *const u8));
+            let _ = write_c_str(&display);
         }
-        syscall(Yield);
+        let _ = yield_now();
     }
 }
 
+/// Same as [user_task_counter], but with the id taken from a [CounterConfig].
+fn user_task_configured_counter(config: &'static CounterConfig) -> ! {
+    user_task_counter(config.id)
+}
+
 /// pid2
 fn user_task_pid_2() -> ! {
     let bus: BusInterface = DevicePeripherals::take();
@@ -71,46 +85,66 @@ fn user_task_pid_2() -> ! {
     loop {
         #[cfg(feature = "semihosting")]
         {
-            let user_input = syscall(SemihostingReadC);
-            if let Char(dir) = user_input {
+            if let Ok(dir) = read_char() {
                 match dir.to_ascii_lowercase() as char {
                     // Hitting enter is just another input character. Here we skip it.
                     '\n' => continue,
                     'n' => {
-                        sprint("pid 2 LED North on\n");
+                        let _ = sprint("pid 2 LED North on\n");
                         leds.on(North)
                     }
                     'w' => {
-                        sprint("pid 2 LED West on\n");
+                        let _ = sprint("pid 2 LED West on\n");
                         leds.on(West)
                     }
                     'e' => {
-                        sprint("pid 2 LED East on\n");
+                        let _ = sprint("pid 2 LED East on\n");
                         leds.on(East)
                     }
                     's' => {
-                        sprint("pid 2 LED South on\n");
+                        let _ = sprint("pid 2 LED South on\n");
                         leds.on(South)
                     }
                     _ => {
-                        sprint("pid 2 LED all off\n");
+                        let _ = sprint("pid 2 LED all off\n");
                         leds.all_off()
                     }
                 };
             }
         }
-        syscall(Yield);
+        let _ = yield_now();
     }
 }
 
 /// Kernel main
 #[no_mangle]
 fn kmain() -> ! {
+    #[cfg(feature = "semihosting")]
+    lake_rtos_rt::panic::set_panic_sink(lake_rtos_rt::panic::semihosting_sink);
+
     let mut cp = CorePeripherals::take().unwrap();
     let system_timer = cp.take_system_timer().unwrap();
 
-    let mut p = Scheduler::init(system_timer, RoundRobin(Some(0x1F40))).unwrap();
-    p.create_process(user_task_pid_1).unwrap();
-    p.create_process(user_task_pid_2).unwrap();
+    let mut p = Scheduler::init(
+        system_timer,
+        RoundRobin(Some(Duration::from_millis(1).as_cycles() as u32)),
+    )
+    .unwrap();
+    #[cfg(feature = "mpu-stack-guard")]
+    p.set_stack_guard(cp.take_mpu().unwrap());
+    p.create_process_with_arg(user_task_counter, 1, 0x1000, 1, false)
+        .unwrap();
+    // Accesses RCC and GPIO directly and therefore needs to be privileged.
+    p.create_process(user_task_pid_2, 0x1000, 1, true).unwrap();
+    p.create_process_with_arg(user_task_counter, 2, 0x1000, 1, false)
+        .unwrap();
+    p.create_process_with_ref(
+        user_task_configured_counter,
+        &COUNTER_CONFIG,
+        0x1000,
+        1,
+        false,
+    )
+    .unwrap();
     p.start_scheduling()
 }
@@ -1,4 +1,5 @@
 .cpu cortex-m4
+.fpu fpv4-sp-d16
 .syntax unified
 .thumb
 
@@ -12,32 +13,112 @@ __breakpoint:
     bkpt
     bx lr
 
-.global __context_switch
-// r0 and r1 are holding the addresses of corresponding struct field psp
-// * r0: *psp next process
-// * r1: *psp from process (can be 0 when first switch is to pid0)
-__context_switch:
+.global PendSV
+.thumb_func
+// # PendSV exception
+//
+// schedule_PendSV selects the next process and returns a pointer to CONTEXT_SWITCH,
+// which holds the addresses of the corresponding struct field psp, or 0 when the
+// current process keeps running.
+// * [r0]: *psp from process (0 on the first switch or when the process terminated)
+// * [r0, #4]: *psp next process
+// * [r0, #8]: nPRIV of the next process, 1 for unprivileged thread mode
+//
+// lr holds EXC_RETURN. Bit 4 is cleared when the process used the FPU, so its frame is
+// extended and s16-s31 have to be saved and restored as well.
+PendSV:
+    push {r4, lr}
+    bl schedule_PendSV
+    pop {r4, lr}
+    cbz r0, 2f
+
+    ldr r1, [r0]
+    ldr r3, [r0, #8]
+    ldr r0, [r0, #4]
+
     // Saves current process when r1 != 0
-    mov r2, #0x0
-    cmp r2, r1
-    ITTT NE
-    mrsne r2, psp
-    stmdbne r2, {r4, r5, r6, r7, r8, r9, r10, r11, lr}
+    cbz r1, 1f
+    mrs r2, psp
+    tst lr, #0x10
+    it eq
+    vstmdbeq r2!, {s16-s31}
+    stmdb r2!, {r4, r5, r6, r7, r8, r9, r10, r11, lr}
     // Saves current psp in array
-    stmne r1, {r2}
+    str r2, [r1]
 
+1:
     // Loads new process
-    ldm r0, {r0}
-    ldmdb r0, {r4, r5, r6, r7, r8, r9, r10, r11, lr}
+    ldr r0, [r0]
+    ldmia r0!, {r4, r5, r6, r7, r8, r9, r10, r11, lr}
+    tst lr, #0x10
+    it eq
+    vldmiaeq r0!, {s16-s31}
     msr psp, r0
+    // Sets the privilege of the new process
+    mrs r2, control
+    bic r2, r2, #0x1
+    orr r2, r2, r3
+    msr control, r2
     isb
 
+2:
     bx lr
 
+.global HardFault
+.global MemManage
+.global BusFault
+.global UsageFault
+// # Fault exceptions
+//
+// Calls handle_fault_exception with
+// * r0: *exception frame stacked on the msp or psp, depending on bit 2 of EXC_RETURN
+// * r1: EXC_RETURN
+// * r2: IPSR, holds the exception number
+.thumb_func
+HardFault:
+.thumb_func
+MemManage:
+.thumb_func
+BusFault:
+.thumb_func
+UsageFault:
+    tst lr, #0x4
+    ite eq
+    mrseq r0, msp
+    mrsne r0, psp
+    mov r1, lr
+    mrs r2, ipsr
+    b handle_fault_exception
+
+.global SVCall
+.thumb_func
+// # SVCall exception
+//
+// Calls handle_SVCall with
+// * r0: *exception frame stacked on the msp or psp, depending on bit 2 of EXC_RETURN
+// * r1: EXC_RETURN
+SVCall:
+    tst lr, #0x4
+    ite eq
+    mrseq r0, msp
+    mrsne r0, psp
+    mov r1, lr
+    b handle_SVCall
+
 .global __syscall
+// # Supervisor call
+//
+// r0 points to the registers r0-r3 and r12 passed to the supervisor call. r12 holds its
+// number, r0-r3 its arguments. The kernel writes the result to the stacked r0-r2, which are
+// stored back afterwards.
 __syscall:
-    svc 0;
-    bx lr
+    push {r4, lr}
+    mov r4, r0
+    ldr r12, [r4, #16]
+    ldmia r4, {r0, r1, r2, r3}
+    svc 0
+    stmia r4, {r0, r1, r2}
+    pop {r4, pc}
 
 .global __sys_write0
 __sys_write0:
@@ -60,7 +141,21 @@ __sys_readc:
     bkpt 0xAB ;
     bx lr
 
-.global __get_r0
-__get_r0:
+.global __disable_interrupts
+// Returns the previous value of PRIMASK in r0, which __restore_interrupts takes.
+__disable_interrupts:
+    mrs r0, primask
+    cpsid i
+    bx lr
+
+.global __restore_interrupts
+__restore_interrupts:
+    msr primask, r0
+    bx lr
+
+.global __memory_barrier
+__memory_barrier:
+    dsb
+    isb
     bx lr
     
\ No newline at end of file
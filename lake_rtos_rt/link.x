@@ -9,6 +9,13 @@ MEMORY
   SRAM (rwx) : ORIGIN = 0x20000000, LENGTH = 40K
 }
 
+/* Size of the region holding the stacks of all processes. The kernel allocates the stack
+of each process within this region. */
+_stacks_size = 24K;
+
+/* Minimum space that has to be left for the main stack at the top end of SRAM. */
+_main_stack_size_min = 4K;
+
 /* The Entry section expects the symbol name of the first executable 
 piece of code which will be loaded into the processor. Logically it is
 the first part of the .text section. */
@@ -74,6 +81,23 @@ SECTIONS
 
   _sidata = LOADADDR(.data);
 
+  /* .stacks is where the process stacks are placed. It is not initialized at startup. */
+  .stacks (NOLOAD) : ALIGN(32)
+  {
+    _sstacks = .;
+    . += _stacks_size;
+    _estacks = .;
+  } > SRAM
+
+  /* .uninit is neither zeroed nor initialized at startup. Its content survives a warm reset. */
+  .uninit (NOLOAD) : ALIGN(4)
+  {
+    *(.uninit .uninit.*);
+  } > SRAM
+
+  ASSERT(ORIGIN(SRAM) + LENGTH(SRAM) - (ADDR(.uninit) + SIZEOF(.uninit)) >= _main_stack_size_min,
+    "Process stacks overlap with the main stack. Reduce _stacks_size in link.x.");
+
   PROVIDE(NMI = DefaultExceptionHandler);
   PROVIDE(HardFault = DefaultExceptionHandler);
   PROVIDE(MemManage = DefaultExceptionHandler);
@@ -7,7 +7,9 @@
 
 #![no_std]
 
-use core::{panic::PanicInfo, ptr};
+pub mod panic;
+
+use core::ptr;
 
 /// Mandatory resetfunction at adress 0x08000004.
 /// Gets called after power on the cpu.
@@ -30,6 +32,20 @@ pub unsafe extern "C" fn Reset() -> ! {
     let count = &_edata as *const u8 as usize - &_sdata as *const u8 as usize;
     ptr::copy_nonoverlapping(&_sidata as *const u8, &mut _sdata as *mut u8, count);
 
+    // Enable the FPU with full access to the coprocessors CP10 and CP11 (CPACR)
+    let cpacr = 0xE000_ED88 as *mut u32;
+    cpacr.write_volatile(cpacr.read_volatile() | (0b1111 << 20));
+
+    // Automatic state preservation of the FPU on exception entry with lazy stacking.
+    // Bit 31 ASPEN and Bit 30 LSPEN of FPCCR
+    let fpccr = 0xE000_EF34 as *mut u32;
+    fpccr.write_volatile(fpccr.read_volatile() | (0b11 << 30));
+
+    extern "C" {
+        fn __memory_barrier();
+    }
+    __memory_barrier();
+
     // reference to target function
     extern "Rust" {
         fn kmain() -> !;
@@ -45,10 +61,6 @@ pub unsafe extern "C" fn Reset() -> ! {
 #[no_mangle]
 pub static RESET_VECTOR: unsafe extern "C" fn() -> ! = Reset;
 
-#[panic_handler]
-fn panic(_panic: &PanicInfo<'_>) -> ! {
-    loop {}
-}
 pub union Vector {
     reserved: u32,
     handler: unsafe extern "C" fn(),
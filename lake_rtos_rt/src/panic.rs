@@ -0,0 +1,155 @@
+//! # Panic
+//!
+//! The panic handler writes the message and the location of a panic to the [PanicSink] set
+//! with [set_panic_sink]. Afterwards it halts with a breakpoint or, with the feature
+//! `panic-reset`, resets the system via `AIRCR.SYSRESETREQ`.
+//!
+//! Available sinks are [semihosting_sink] and [ram_sink]. A sink is a plain function, so
+//! further outputs can be added the same way. The [ram_sink] keeps the last panic in a buffer
+//! that survives a warm reset and can be read with [take_last_panic] after the reset.
+//!
+//! *NOTE: A panic of an unprivileged process can not reset the system or disable interrupts.
+//! Accessing the RAM buffer may be denied by the MPU as well.*
+
+use core::{fmt, mem::MaybeUninit, panic::PanicInfo, ptr, str};
+
+/// Receives the formatted panic report piece by piece.
+pub type PanicSink = fn(text: &str);
+
+/// Size of the buffer of the [ram_sink] in bytes.
+pub const PANIC_BUFFER_SIZE: usize = 256;
+
+/// Marks the buffer of the [ram_sink] as valid. Any other value is random content of the RAM
+/// after power on.
+const PANIC_BUFFER_MAGIC: u32 = 0xC0FF_EE00;
+
+extern "C" {
+    fn __sys_writec(char: *const u8);
+    fn __disable_interrupts();
+    fn __breakpoint();
+}
+
+static mut PANIC_SINK: Option<PanicSink> = None;
+
+/// Holds the last panic report of the [ram_sink].
+#[repr(C)]
+struct PanicBuffer {
+    magic: u32,
+    len: usize,
+    data: [u8; PANIC_BUFFER_SIZE],
+}
+
+/// Placed in `.uninit`, which is neither zeroed nor initialized by [Reset][crate::Reset].
+#[link_section = ".uninit.PANIC_BUFFER"]
+static mut PANIC_BUFFER: MaybeUninit<PanicBuffer> = MaybeUninit::uninit();
+
+/// Returns the [PANIC_BUFFER], which may hold random content.
+fn panic_buffer() -> &'static mut PanicBuffer {
+    // Every bit pattern is a valid PanicBuffer, the magic tells whether it holds a report.
+    unsafe { &mut *ptr::addr_of_mut!(PANIC_BUFFER).cast::<PanicBuffer>() }
+}
+
+/// Sets the sink the panic handler writes to. Without a sink, a panic is not reported.
+pub fn set_panic_sink(sink: PanicSink) {
+    unsafe { PANIC_SINK = Some(sink) };
+}
+
+/// Returns the sink set with [set_panic_sink]. Other reports, e.g. of faults, may write to it
+/// as well.
+pub fn panic_sink() -> Option<PanicSink> {
+    unsafe { PANIC_SINK }
+}
+
+/// Writes to the debug channel of the host via ARM semihosting.
+pub fn semihosting_sink(text: &str) {
+    for char in text.bytes() {
+        unsafe { __sys_writec(&char) };
+    }
+}
+
+/// Appends to the buffer in RAM. Text exceeding [PANIC_BUFFER_SIZE] is dropped.
+pub fn ram_sink(text: &str) {
+    let buffer = panic_buffer();
+    if buffer.magic != PANIC_BUFFER_MAGIC {
+        buffer.magic = PANIC_BUFFER_MAGIC;
+        buffer.len = 0;
+    }
+    let len = text.len().min(PANIC_BUFFER_SIZE - buffer.len);
+    buffer.data[buffer.len..buffer.len + len].copy_from_slice(&text.as_bytes()[..len]);
+    buffer.len += len;
+}
+
+/// Returns the panic report the [ram_sink] wrote before the last warm reset and clears it,
+/// so it is only returned once.
+pub fn take_last_panic() -> Option<&'static str> {
+    let buffer = panic_buffer();
+    if buffer.magic != PANIC_BUFFER_MAGIC || buffer.len > PANIC_BUFFER_SIZE {
+        return None;
+    }
+    buffer.magic = 0;
+
+    let data = &buffer.data[..buffer.len];
+    // The buffer might end within a character.
+    match str::from_utf8(data) {
+        Ok(text) => Some(text),
+        Err(error) => str::from_utf8(&data[..error.valid_up_to()]).ok(),
+    }
+}
+
+/// Invalidates the buffer of the [ram_sink], so it only holds the current panic.
+fn clear_ram_buffer() {
+    panic_buffer().magic = 0;
+}
+
+/// Forwards formatted text to a [PanicSink], e.g. for other reports written to the same sink.
+pub struct SinkWriter(pub PanicSink);
+
+impl fmt::Write for SinkWriter {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        (self.0)(text);
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo<'_>) -> ! {
+    unsafe { __disable_interrupts() };
+    clear_ram_buffer();
+
+    if let Some(sink) = panic_sink() {
+        use fmt::Write;
+        let mut writer = SinkWriter(sink);
+        let _ = match info.location() {
+            Some(location) => writeln!(
+                writer,
+                "panicked at {}:{}: {}",
+                location.file(),
+                location.line(),
+                info.message()
+            ),
+            None => writeln!(writer, "panicked: {}", info.message()),
+        };
+    }
+
+    halt_or_reset()
+}
+
+/// Halts with a breakpoint, so an attached debugger stops at the panic.
+#[cfg(not(feature = "panic-reset"))]
+fn halt_or_reset() -> ! {
+    unsafe { __breakpoint() };
+    loop {}
+}
+
+/// Requests a system reset by setting `Bit 2 SYSRESETREQ` of the AIRCR.
+#[cfg(feature = "panic-reset")]
+fn halt_or_reset() -> ! {
+    let aircr = 0xE000_ED0C as *mut u32;
+    unsafe {
+        // The upper half has to be written with the key 0x05FA. The priority grouping in bits
+        // 8 to 10 is kept.
+        let prigroup = aircr.read_volatile() & (0b111 << 8);
+        aircr.write_volatile(0x05FA_0000 | prigroup | (1 << 2));
+    }
+    loop {}
+}